@@ -0,0 +1,102 @@
+use core::fmt;
+
+use super::sdt::{Sdt, GenericAddress};
+
+pub const SIGNATURE: &[u8; 4] = b"FACP";
+
+// IA-PC boot architecture flags
+pub const BOOT_LEGACY_DEVICES: u16 = 1 << 0;
+pub const BOOT_8042: u16 = 1 << 1;
+pub const BOOT_VGA_NOT_PRESENT: u16 = 1 << 2;
+pub const BOOT_MSI_NOT_SUPPORTED: u16 = 1 << 3;
+pub const BOOT_CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+// Fixed feature flags
+pub const FLAG_TIMER_32_BIT: u32 = 1 << 8;
+pub const FLAG_RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+// Fixed ACPI Description Table, which describes the power management hardware
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub dsdt_address: u64,
+    pub sci_interrupt: u16,
+    // Port to write `acpi_enable` or `acpi_disable` to for switching ACPI mode, zero if the
+    // system is always in ACPI mode
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_control_block: u32,
+    pub pm_timer_block: u32,
+    // CMOS RAM index of the century, zero if the RTC has no century register
+    pub century_register: u8,
+    pub boot_architecture_flags: u16,
+    pub flags: u32,
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    pub fn parse(sdt: &Sdt) -> Option<Fadt> {
+        // The 64 bit DSDT address was added in ACPI 2.0 and takes precedence when set
+        let dsdt_address = match sdt.read_at::<u64>(140).unwrap_or(0) {
+            0 => sdt.read_at::<u32>(40)? as u64,
+            address => address,
+        };
+        let flags = sdt.read_at(112).unwrap_or(0);
+
+        Some(Fadt {
+            dsdt_address,
+            sci_interrupt: sdt.read_at(46)?,
+            smi_command_port: sdt.read_at(48)?,
+            acpi_enable: sdt.read_at(52)?,
+            acpi_disable: sdt.read_at(53)?,
+            pm1a_control_block: sdt.read_at(64)?,
+            pm_timer_block: sdt.read_at(76)?,
+            century_register: sdt.read_at(108).unwrap_or(0),
+            // ACPI 1.0 tables have no boot architecture flags and assume a PC with legacy devices
+            boot_architecture_flags: match sdt.revision {
+                0 | 1 => BOOT_LEGACY_DEVICES | BOOT_8042,
+                _ => sdt.read_at(109).unwrap_or(0),
+            },
+            flags,
+            reset_register: if flags & FLAG_RESET_REGISTER_SUPPORTED != 0 {
+                GenericAddress::read_from(sdt, 116)
+            }
+            else {
+                None
+            },
+            reset_value: sdt.read_at(128).unwrap_or(0),
+        })
+    }
+
+    pub fn has_8042(&self) -> bool {
+        self.boot_architecture_flags & BOOT_8042 != 0
+    }
+
+    pub fn has_cmos_rtc(&self) -> bool {
+        self.boot_architecture_flags & BOOT_CMOS_RTC_NOT_PRESENT == 0
+    }
+
+    pub fn has_vga(&self) -> bool {
+        self.boot_architecture_flags & BOOT_VGA_NOT_PRESENT == 0
+    }
+}
+
+impl fmt::Display for Fadt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "FADT: DSDT at {:#x}, SCI IRQ {}, SMI command port {:#x}",
+            self.dsdt_address, self.sci_interrupt, self.smi_command_port)?;
+        writeln!(f, "  PM1a control {:#x}, PM timer {:#x} ({} bit)", self.pm1a_control_block,
+            self.pm_timer_block, if self.flags & FLAG_TIMER_32_BIT != 0 { 32 } else { 24 })?;
+        writeln!(f, "  century register {:#x}, boot flags {:#06x}{}{}{}{}", self.century_register,
+            self.boot_architecture_flags,
+            if self.has_8042() { ", 8042" } else { "" },
+            if self.has_cmos_rtc() { ", CMOS RTC" } else { "" },
+            if self.has_vga() { ", VGA" } else { "" },
+            if self.boot_architecture_flags & BOOT_MSI_NOT_SUPPORTED != 0 { ", no MSI" } else { "" })?;
+        if let Some(reset_register) = self.reset_register {
+            writeln!(f, "  reset register {}, value {:#x}", reset_register, self.reset_value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use core::fmt;
+
+use super::sdt::{Sdt, GenericAddress};
+
+pub const SIGNATURE: &[u8; 4] = b"HPET";
+
+// High Precision Event Timer description
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub hardware_revision: u8,
+    pub comparator_count: u8,
+    pub counter_64_bit: bool,
+    pub legacy_replacement: bool,
+    pub pci_vendor_id: u16,
+    pub base_address: GenericAddress,
+    pub number: u8,
+    // Minimum clock ticks for periodic mode without losing interrupts
+    pub minimum_tick: u16,
+}
+
+impl Hpet {
+    pub fn parse(sdt: &Sdt) -> Option<Hpet> {
+        let block_id: u32 = sdt.read_at(36)?;
+        Some(Hpet {
+            hardware_revision: block_id as u8,
+            comparator_count: ((block_id >> 8) & 0x1f) as u8 + 1,
+            counter_64_bit: block_id & 1 << 13 != 0,
+            legacy_replacement: block_id & 1 << 15 != 0,
+            pci_vendor_id: (block_id >> 16) as u16,
+            base_address: GenericAddress::read_from(sdt, 40)?,
+            number: sdt.read_at(52)?,
+            minimum_tick: sdt.read_at(53)?,
+        })
+    }
+}
+
+impl fmt::Display for Hpet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "HPET {}: {}, {} comparators, {} bit counter{}, minimum tick {}",
+            self.number, self.base_address, self.comparator_count,
+            if self.counter_64_bit { 64 } else { 32 },
+            if self.legacy_replacement { ", legacy replacement" } else { "" },
+            self.minimum_tick)
+    }
+}
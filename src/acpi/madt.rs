@@ -0,0 +1,185 @@
+use alloc::Vec;
+use core::fmt;
+
+use interrupts::ioapic::{Polarity, TriggerMode};
+use super::sdt::Sdt;
+
+pub const SIGNATURE: &[u8; 4] = b"APIC";
+
+const ENTRIES_OFFSET: usize = 44;
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_NMI: u8 = 4;
+const ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+const ENTRY_LOCAL_X2APIC: u8 = 9;
+
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+
+// Multiple APIC Description Table, which lists the interrupt controllers of the system
+#[derive(Debug)]
+pub struct Madt {
+    pub local_apic_address: u64,
+    // Whether the system also has dual 8259 PICs, which must be disabled to use the APICs
+    pub has_legacy_pics: bool,
+    pub local_apics: Vec<LocalApic>,
+    pub io_apics: Vec<IoApic>,
+    pub source_overrides: Vec<InterruptSourceOverride>,
+    pub local_apic_nmis: Vec<LocalApicNmi>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApic {
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    pub enabled: bool,
+    // A disabled processor that can be brought online later
+    pub online_capable: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+// ISA IRQ `irq` is delivered on global system interrupt `gsi`
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptSourceOverride {
+    pub bus: u8,
+    pub irq: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+// LINT pin of a local APIC that is connected to NMI
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApicNmi {
+    // `None` if it applies to all processors
+    pub processor_uid: Option<u32>,
+    pub lint: u8,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+impl Madt {
+    pub fn parse(sdt: &Sdt) -> Option<Madt> {
+        let mut madt = Madt {
+            local_apic_address: sdt.read_at::<u32>(36)? as u64,
+            has_legacy_pics: sdt.read_at::<u32>(40)? & 1 != 0,
+            local_apics: Vec::new(),
+            io_apics: Vec::new(),
+            source_overrides: Vec::new(),
+            local_apic_nmis: Vec::new(),
+        };
+
+        let mut offset = ENTRIES_OFFSET;
+        while offset + 2 <= sdt.length {
+            let entry_type: u8 = sdt.read_at(offset)?;
+            let length = sdt.read_at::<u8>(offset + 1)? as usize;
+            if length < 2 || offset + length > sdt.length {
+                break;
+            }
+
+            match entry_type {
+                ENTRY_LOCAL_APIC => {
+                    let flags: u32 = sdt.read_at(offset + 4)?;
+                    madt.local_apics.push(LocalApic {
+                        processor_uid: sdt.read_at::<u8>(offset + 2)? as u32,
+                        apic_id: sdt.read_at::<u8>(offset + 3)? as u32,
+                        enabled: flags & PROCESSOR_ENABLED != 0,
+                        online_capable: flags & PROCESSOR_ONLINE_CAPABLE != 0,
+                    });
+                },
+                ENTRY_LOCAL_X2APIC => {
+                    let flags: u32 = sdt.read_at(offset + 8)?;
+                    madt.local_apics.push(LocalApic {
+                        processor_uid: sdt.read_at(offset + 12)?,
+                        apic_id: sdt.read_at(offset + 4)?,
+                        enabled: flags & PROCESSOR_ENABLED != 0,
+                        online_capable: flags & PROCESSOR_ONLINE_CAPABLE != 0,
+                    });
+                },
+                ENTRY_IO_APIC => {
+                    madt.io_apics.push(IoApic {
+                        id: sdt.read_at(offset + 2)?,
+                        address: sdt.read_at(offset + 4)?,
+                        gsi_base: sdt.read_at(offset + 8)?,
+                    });
+                },
+                ENTRY_SOURCE_OVERRIDE => {
+                    let (polarity, trigger_mode) = decode_inti_flags(sdt.read_at(offset + 8)?);
+                    madt.source_overrides.push(InterruptSourceOverride {
+                        bus: sdt.read_at(offset + 2)?,
+                        irq: sdt.read_at(offset + 3)?,
+                        gsi: sdt.read_at(offset + 4)?,
+                        polarity,
+                        trigger_mode,
+                    });
+                },
+                ENTRY_LOCAL_APIC_NMI => {
+                    let (polarity, trigger_mode) = decode_inti_flags(sdt.read_at(offset + 3)?);
+                    let processor_uid: u8 = sdt.read_at(offset + 2)?;
+                    madt.local_apic_nmis.push(LocalApicNmi {
+                        processor_uid: if processor_uid == 0xff { None } else { Some(processor_uid as u32) },
+                        lint: sdt.read_at(offset + 5)?,
+                        polarity,
+                        trigger_mode,
+                    });
+                },
+                ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE => {
+                    madt.local_apic_address = sdt.read_at(offset + 4)?;
+                },
+                _ => {},
+            }
+
+            offset += length;
+        }
+
+        Some(madt)
+    }
+}
+
+// Polarity and trigger mode of an interrupt in the MPS INTI flags format. "Conforms to the bus"
+// means the ISA defaults, which is all the kernel supports.
+fn decode_inti_flags(flags: u16) -> (Polarity, TriggerMode) {
+    let polarity = match flags & 0b11 {
+        0b11 => Polarity::ActiveLow,
+        _ => Polarity::ActiveHigh,
+    };
+    let trigger_mode = match (flags >> 2) & 0b11 {
+        0b11 => TriggerMode::Level,
+        _ => TriggerMode::Edge,
+    };
+    (polarity, trigger_mode)
+}
+
+impl fmt::Display for Madt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "MADT: local APIC at {:#x}{}", self.local_apic_address,
+            if self.has_legacy_pics { ", legacy PICs present" } else { "" })?;
+        for apic in &self.local_apics {
+            writeln!(f, "  CPU {}: APIC ID {}{}", apic.processor_uid, apic.apic_id,
+                if apic.enabled { "" } else if apic.online_capable { " (offline)" } else { " (disabled)" })?;
+        }
+        for io_apic in &self.io_apics {
+            writeln!(f, "  I/O APIC {} at {:#x}, GSI base {}", io_apic.id, io_apic.address, io_apic.gsi_base)?;
+        }
+        for source_override in &self.source_overrides {
+            writeln!(f, "  IRQ {} -> GSI {}, {:?}, {:?}", source_override.irq, source_override.gsi,
+                source_override.polarity, source_override.trigger_mode)?;
+        }
+        for nmi in &self.local_apic_nmis {
+            match nmi.processor_uid {
+                Some(uid) => write!(f, "  NMI on LINT{} of CPU {}", nmi.lint, uid)?,
+                None => write!(f, "  NMI on LINT{} of all CPUs", nmi.lint)?,
+            }
+            writeln!(f, ", {:?}, {:?}", nmi.polarity, nmi.trigger_mode)?;
+        }
+        Ok(())
+    }
+}
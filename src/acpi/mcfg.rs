@@ -0,0 +1,54 @@
+use alloc::Vec;
+use core::fmt;
+
+use super::sdt::Sdt;
+
+pub const SIGNATURE: &[u8; 4] = b"MCFG";
+
+const ENTRIES_OFFSET: usize = 44;
+const ENTRY_SIZE: usize = 16;
+
+// PCI Express memory mapped configuration space description
+#[derive(Debug)]
+pub struct Mcfg {
+    pub entries: Vec<McfgEntry>,
+}
+
+// Configuration space of buses `start_bus..=end_bus` of a PCI segment group
+#[derive(Debug, Clone, Copy)]
+pub struct McfgEntry {
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl Mcfg {
+    pub fn parse(sdt: &Sdt) -> Option<Mcfg> {
+        let mut entries = Vec::new();
+        let mut offset = ENTRIES_OFFSET;
+        while offset + ENTRY_SIZE <= sdt.length {
+            entries.push(McfgEntry {
+                base_address: sdt.read_at(offset)?,
+                segment_group: sdt.read_at(offset + 8)?,
+                start_bus: sdt.read_at(offset + 10)?,
+                end_bus: sdt.read_at(offset + 11)?,
+            });
+            offset += ENTRY_SIZE;
+        }
+        Some(Mcfg {
+            entries,
+        })
+    }
+}
+
+impl fmt::Display for Mcfg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "MCFG:")?;
+        for entry in &self.entries {
+            writeln!(f, "  segment {} buses {}-{} at {:#x}",
+                entry.segment_group, entry.start_bus, entry.end_bus, entry.base_address)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use alloc::Vec;
+use core::fmt;
+use multiboot2::BootInformation;
+use spin::Once;
+
+use memory::MemoryController;
+use memory::paging::entry::EntryFlags;
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+mod rsdp;
+mod sdt;
+
+#[allow(unused_imports)]
+pub use self::fadt::Fadt;
+#[allow(unused_imports)]
+pub use self::hpet::Hpet;
+pub use self::madt::Madt;
+pub use self::mcfg::Mcfg;
+#[allow(unused_imports)]
+pub use self::sdt::{Sdt, GenericAddress, AddressSpace};
+use self::rsdp::Rsdp;
+use self::sdt::ascii;
+
+// Multiboot2 tags with a copy of the RSDP
+const MULTIBOOT_TAG_END: u32 = 0;
+const MULTIBOOT_TAG_RSDP_V1: u32 = 14;
+const MULTIBOOT_TAG_RSDP_V2: u32 = 15;
+
+// The BIOS data area holds the real mode segment of the extended BIOS data area
+const BDA_EBDA_SEGMENT: usize = 0x40e;
+const EBDA_SEARCH_SIZE: usize = 1024;
+// Anything outside of this is not a sane EBDA, e.g. because the frame of the BDA was reused
+const EBDA_MIN: usize = 0x8_0000;
+const EBDA_MAX: usize = 0xa_0000;
+const BIOS_AREA_START: usize = 0xe_0000;
+const BIOS_AREA_END: usize = 0x10_0000;
+
+// Tables larger than this are considered corrupt
+const MAX_TABLE_SIZE: usize = 1024 * 1024;
+
+static TABLES: Once<AcpiTables> = Once::new();
+
+pub struct AcpiTables {
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    // Every table the RSDT or XSDT points to with a valid checksum
+    pub tables: Vec<Sdt>,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+    pub mcfg: Option<Mcfg>,
+}
+
+impl fmt::Display for AcpiTables {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ACPI revision {}, OEM {}", self.revision, ascii(&self.oem_id))?;
+        for sdt in &self.tables {
+            writeln!(f, "  {}", sdt)?;
+        }
+        if let Some(ref madt) = self.madt {
+            write!(f, "{}", madt)?;
+        }
+        if let Some(ref fadt) = self.fadt {
+            write!(f, "{}", fadt)?;
+        }
+        if let Some(ref hpet) = self.hpet {
+            write!(f, "{}", hpet)?;
+        }
+        if let Some(ref mcfg) = self.mcfg {
+            write!(f, "{}", mcfg)?;
+        }
+        Ok(())
+    }
+}
+
+// Returns the ACPI tables, or `None` if `init` did not find any
+pub fn tables() -> Option<&'static AcpiTables> {
+    TABLES.try()
+}
+
+// Prints all ACPI tables found at boot to the console, see the `acpi_dump` option
+pub fn dump() {
+    match tables() {
+        Some(tables) => kprint!("{}", tables),
+        None => kprintln!("No ACPI tables found"),
+    }
+}
+
+// Finds the RSDP and parses the tables it points to. The tables stay identity mapped read-only.
+pub fn init(boot_info: &BootInformation, memory_controller: &mut MemoryController) -> Option<&'static AcpiTables> {
+    assert_has_not_been_called!("acpi::init must only be called once!");
+
+    let rsdp = match unsafe { find_rsdp(boot_info, memory_controller) } {
+        Some(rsdp) => rsdp,
+        None => {
+            warn!("No RSDP found");
+            return None;
+        },
+    };
+
+    let root = match rsdp.xsdt_address {
+        Some(address) => unsafe { map_table(memory_controller, address as usize) },
+        None => unsafe { map_table(memory_controller, rsdp.rsdt_address as usize) },
+    };
+    let root = match root {
+        Some(root) => root,
+        None => {
+            warn!("The root table is invalid");
+            return None;
+        },
+    };
+
+    // The XSDT has 64 bit pointers, the RSDT 32 bit ones
+    let pointer_size = if rsdp.xsdt_address.is_some() { 8 } else { 4 };
+    let mut tables = Vec::new();
+    let mut offset = sdt::HEADER_SIZE;
+    while offset + pointer_size <= root.length {
+        let address = if pointer_size == 8 {
+            root.read_at::<u64>(offset).unwrap() as usize
+        }
+        else {
+            root.read_at::<u32>(offset).unwrap() as usize
+        };
+        match unsafe { map_table(memory_controller, address) } {
+            Some(sdt) => tables.push(sdt),
+            None => warn!("Skipping invalid table at {:#x}", address),
+        }
+        offset += pointer_size;
+    }
+
+    let acpi_tables = AcpiTables {
+        revision: rsdp.revision,
+        oem_id: rsdp.oem_id,
+        madt: find_table(&tables, madt::SIGNATURE).and_then(Madt::parse),
+        fadt: find_table(&tables, fadt::SIGNATURE).and_then(Fadt::parse),
+        hpet: find_table(&tables, hpet::SIGNATURE).and_then(Hpet::parse),
+        mcfg: find_table(&tables, mcfg::SIGNATURE).and_then(Mcfg::parse),
+        tables,
+    };
+
+    info!("ACPI {} tables from {}", acpi_tables.tables.len(), ascii(&acpi_tables.oem_id));
+    Some(TABLES.call_once(|| acpi_tables))
+}
+
+fn find_table<'a>(tables: &'a [Sdt], signature: &[u8; 4]) -> Option<&'a Sdt> {
+    tables.iter().find(|sdt| sdt.signature == *signature)
+}
+
+// Maps the table at `address` and validates its checksum
+unsafe fn map_table(memory_controller: &mut MemoryController, address: usize) -> Option<Sdt> {
+    if address == 0 {
+        return None;
+    }
+    memory_controller.identity_map_physical(address, sdt::HEADER_SIZE, EntryFlags::NO_EXECUTE);
+    let sdt = Sdt::read(address);
+    if sdt.length < sdt::HEADER_SIZE || sdt.length > MAX_TABLE_SIZE {
+        return None;
+    }
+    memory_controller.identity_map_physical(address, sdt.length, EntryFlags::NO_EXECUTE);
+
+    if sdt.is_valid() {
+        Some(sdt)
+    }
+    else {
+        None
+    }
+}
+
+// Takes the RSDP from the multiboot information if the boot loader provided it, otherwise
+// searches the places the BIOS puts it
+unsafe fn find_rsdp(boot_info: &BootInformation, memory_controller: &mut MemoryController) -> Option<Rsdp> {
+    // Multiboot2 0.3 has no accessor for these tags, so walk the tags by hand. They follow the
+    // 8 byte fixed part of the information, each 8 byte aligned.
+    // The ACPI 2.0 RSDP is preferred since only it points to the XSDT.
+    let mut v1_rsdp = None;
+    let mut tag = boot_info.start_address() + 8;
+    while tag + 8 <= boot_info.end_address() {
+        let tag_type: u32 = sdt::read(tag);
+        let tag_size = sdt::read::<u32>(tag + 4) as usize;
+        if tag_type == MULTIBOOT_TAG_END || tag_size < 8 {
+            break;
+        }
+        if tag_type == MULTIBOOT_TAG_RSDP_V2 {
+            if let Some(rsdp) = Rsdp::read(tag + 8) {
+                return Some(rsdp);
+            }
+        }
+        else if tag_type == MULTIBOOT_TAG_RSDP_V1 {
+            v1_rsdp = Rsdp::read(tag + 8);
+        }
+        tag += (tag_size + 7) & !7;
+    }
+
+    v1_rsdp.or_else(|| search_bios_areas(memory_controller))
+}
+
+unsafe fn search_bios_areas(memory_controller: &mut MemoryController) -> Option<Rsdp> {
+    // Low memory is not mapped, map it only for the search
+    let mapping = memory_controller.identity_map_physical(BDA_EBDA_SEGMENT, 2, EntryFlags::NO_EXECUTE);
+    let ebda = (sdt::read::<u16>(BDA_EBDA_SEGMENT) as usize) << 4;
+    memory_controller.identity_unmap_physical(mapping);
+
+    if ebda >= EBDA_MIN && ebda + EBDA_SEARCH_SIZE <= EBDA_MAX {
+        let mapping = memory_controller.identity_map_physical(ebda, EBDA_SEARCH_SIZE, EntryFlags::NO_EXECUTE);
+        let rsdp = Rsdp::search(ebda, ebda + EBDA_SEARCH_SIZE);
+        memory_controller.identity_unmap_physical(mapping);
+        if rsdp.is_some() {
+            return rsdp;
+        }
+    }
+
+    let size = BIOS_AREA_END - BIOS_AREA_START;
+    let mapping = memory_controller.identity_map_physical(BIOS_AREA_START, size, EntryFlags::NO_EXECUTE);
+    let rsdp = Rsdp::search(BIOS_AREA_START, BIOS_AREA_END);
+    memory_controller.identity_unmap_physical(mapping);
+    rsdp
+}
@@ -0,0 +1,61 @@
+use super::sdt::{read, checksum};
+
+const SIGNATURE: &[u8; 8] = b"RSD PTR ";
+// Size of the ACPI 1.0 structure, which is all the checksum covers
+const V1_SIZE: usize = 20;
+pub const V2_SIZE: usize = 36;
+
+// Root System Description Pointer, which points to the RSDT and on ACPI 2.0+ also to the XSDT
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub address: usize,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub xsdt_address: Option<u64>,
+}
+
+impl Rsdp {
+    // Reads and validates the RSDP at `address`. At least `V1_SIZE` bytes must be mapped, and
+    // `V2_SIZE` bytes if the revision says it is an extended RSDP.
+    pub unsafe fn read(address: usize) -> Option<Rsdp> {
+        if read::<[u8; 8]>(address) != *SIGNATURE || !checksum(address, V1_SIZE) {
+            return None;
+        }
+
+        let revision: u8 = read(address + 15);
+        let xsdt_address = if revision >= 2 {
+            let length = read::<u32>(address + 20) as usize;
+            if length < V2_SIZE || !checksum(address, V2_SIZE) {
+                return None;
+            }
+            match read::<u64>(address + 24) {
+                0 => None,
+                xsdt_address => Some(xsdt_address),
+            }
+        }
+        else {
+            None
+        };
+
+        Some(Rsdp {
+            address,
+            oem_id: read(address + 9),
+            revision,
+            rsdt_address: read(address + 16),
+            xsdt_address,
+        })
+    }
+
+    // Searches `[start, end)` for a valid RSDP, which is always 16 byte aligned
+    pub unsafe fn search(start: usize, end: usize) -> Option<Rsdp> {
+        let mut address = start;
+        while address + V2_SIZE <= end {
+            if let Some(rsdp) = Rsdp::read(address) {
+                return Some(rsdp);
+            }
+            address += 16;
+        }
+        None
+    }
+}
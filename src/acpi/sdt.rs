@@ -0,0 +1,118 @@
+use core::{fmt, ptr, str};
+
+pub const HEADER_SIZE: usize = 36;
+
+// Header every ACPI table (except the RSDP) starts with
+#[derive(Debug, Clone, Copy)]
+pub struct Sdt {
+    pub address: usize,
+    pub signature: [u8; 4],
+    pub length: usize,
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+}
+
+impl Sdt {
+    // Reads the header at `address`, which must be mapped for at least `HEADER_SIZE` bytes
+    pub unsafe fn read(address: usize) -> Sdt {
+        Sdt {
+            address,
+            signature: read(address),
+            length: read::<u32>(address + 4) as usize,
+            revision: read(address + 8),
+            oem_id: read(address + 10),
+            oem_table_id: read(address + 16),
+        }
+    }
+
+    // The whole table must be mapped
+    pub unsafe fn is_valid(&self) -> bool {
+        self.length >= HEADER_SIZE && checksum(self.address, self.length)
+    }
+
+    // Reads the value at `offset` from the start of the table, or `None` if the table is too
+    // short, which happens for fields added in later ACPI revisions
+    pub fn read_at<T: Copy>(&self, offset: usize) -> Option<T> {
+        if offset + ::core::mem::size_of::<T>() <= self.length {
+            Some(unsafe { read(self.address + offset) })
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Sdt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {:#010x}, {} bytes, revision {}, OEM {} {}",
+            ascii(&self.signature), self.address, self.length, self.revision,
+            ascii(&self.oem_id), ascii(&self.oem_table_id))
+    }
+}
+
+// Generic Address Structure, which describes a register in some address space
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub address_space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    PciConfiguration,
+    Other(u8),
+}
+
+impl GenericAddress {
+    pub const SIZE: usize = 12;
+
+    pub fn read_from(sdt: &Sdt, offset: usize) -> Option<GenericAddress> {
+        if offset + GenericAddress::SIZE > sdt.length {
+            return None;
+        }
+        let address_space = match sdt.read_at::<u8>(offset).unwrap() {
+            0 => AddressSpace::SystemMemory,
+            1 => AddressSpace::SystemIo,
+            2 => AddressSpace::PciConfiguration,
+            other => AddressSpace::Other(other),
+        };
+        Some(GenericAddress {
+            address_space,
+            bit_width: sdt.read_at(offset + 1).unwrap(),
+            bit_offset: sdt.read_at(offset + 2).unwrap(),
+            access_size: sdt.read_at(offset + 3).unwrap(),
+            address: sdt.read_at(offset + 4).unwrap(),
+        })
+    }
+}
+
+impl fmt::Display for GenericAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.address_space {
+            AddressSpace::SystemMemory => write!(f, "memory {:#x}", self.address),
+            AddressSpace::SystemIo => write!(f, "port {:#x}", self.address),
+            AddressSpace::PciConfiguration => write!(f, "PCI config {:#x}", self.address),
+            AddressSpace::Other(space) => write!(f, "space {} {:#x}", space, self.address),
+        }
+    }
+}
+
+// ACPI structures are byte packed, so fields can be at any alignment
+pub unsafe fn read<T: Copy>(address: usize) -> T {
+    ptr::read_unaligned(address as *const T)
+}
+
+// All bytes of a table, including its checksum field, add up to zero
+pub unsafe fn checksum(address: usize, length: usize) -> bool {
+    (0..length).fold(0u8, |sum, i| sum.wrapping_add(read::<u8>(address + i))) == 0
+}
+
+pub fn ascii(bytes: &[u8]) -> &str {
+    str::from_utf8(bytes).unwrap_or("?")
+}
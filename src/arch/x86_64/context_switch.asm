@@ -0,0 +1,37 @@
+global switch_context
+global thread_entry
+
+extern thread_start
+
+section .text
+bits 64
+; switch_context(old_stack_pointer: *mut usize, new_stack_pointer: usize)
+; Saves the callee-saved registers on the current stack, stores the stack pointer in
+; `[rdi]` and continues with the registers saved on the stack at `rsi`. The caller saved
+; registers are saved by the Rust caller, which sees an ordinary function call.
+switch_context:
+  push rbx
+  push rbp
+  push r12
+  push r13
+  push r14
+  push r15
+  mov [rdi], rsp
+
+  mov rsp, rsi
+  pop r15
+  pop r14
+  pop r13
+  pop r12
+  pop rbp
+  pop rbx
+  ret
+
+; The first `switch_context` to a new thread returns here. The stack is 16 byte aligned
+; like before a call.
+thread_entry:
+  ; A null frame pointer ends backtraces
+  xor rbp, rbp
+  call thread_start
+  ; `thread_start` never returns
+  ud2
@@ -13,6 +13,8 @@ long_mode_start:
   mov fs, ax
   mov gs, ax
 
+  ; A null frame pointer ends backtraces
+  xor rbp, rbp
   call rust_main
 
   ; Print Okay to Screen
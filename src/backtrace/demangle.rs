@@ -0,0 +1,123 @@
+use core::fmt::{self, Write};
+
+// Formats a legacy mangled Rust symbol like `_ZN6rustos9rust_main17h0123456789abcdefE` as
+// `rustos::rust_main`. Anything else is written as it is.
+pub struct Demangle<'a>(pub &'a str);
+
+impl<'a> fmt::Display for Demangle<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = match mangled_path(self.0) {
+            Some(path) => path,
+            None => return f.write_str(self.0),
+        };
+
+        let mut first = true;
+        for segment in Segments(path) {
+            // Only the last segment can be the hash, which tells apart symbols with the same path
+            if is_hash(segment) {
+                continue;
+            }
+            if !first {
+                f.write_str("::")?;
+            }
+            first = false;
+            write_segment(f, segment)?;
+        }
+        Ok(())
+    }
+}
+
+// Returns the length prefixed segments between `_ZN` and `E` if they are well formed
+fn mangled_path(symbol: &str) -> Option<&str> {
+    if !symbol.starts_with("_ZN") || !symbol.ends_with('E') || symbol.len() < 4 {
+        return None;
+    }
+    let path = &symbol[3..symbol.len() - 1];
+
+    let mut rest = path;
+    while !rest.is_empty() {
+        let (length, digits) = segment_length(rest)?;
+        if digits + length > rest.len() || !rest.is_char_boundary(digits + length) {
+            return None;
+        }
+        rest = &rest[digits + length..];
+    }
+    Some(path)
+}
+
+fn segment_length(s: &str) -> Option<(usize, usize)> {
+    let digits = s.bytes().take_while(|&b| (b as char).is_digit(10)).count();
+    if digits == 0 {
+        return None;
+    }
+    Some((s[..digits].parse().ok()?, digits))
+}
+
+// Iterates over the segments of a path checked by `mangled_path`
+struct Segments<'a>(&'a str);
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let (length, digits) = segment_length(self.0)?;
+        let segment = &self.0[digits..digits + length];
+        self.0 = &self.0[digits + length..];
+        Some(segment)
+    }
+}
+
+fn is_hash(segment: &str) -> bool {
+    segment.len() == 17 && segment.starts_with('h') &&
+        segment[1..].bytes().all(|b| (b as char).is_digit(16))
+}
+
+// Writes a segment with its `$..$` escapes and `..` path separators replaced
+fn write_segment(f: &mut fmt::Formatter, segment: &str) -> fmt::Result {
+    // Segments that would start with an escape get an extra underscore
+    let mut rest = if segment.starts_with("_$") { &segment[1..] } else { segment };
+
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            f.write_str("::")?;
+            rest = &rest[2..];
+        }
+        else if rest.starts_with('$') {
+            let end = match rest[1..].find('$') {
+                Some(end) => end + 1,
+                None => return f.write_str(rest),
+            };
+            match unescape(&rest[1..end]) {
+                Some(c) => f.write_char(c)?,
+                None => f.write_str(&rest[..end + 1])?,
+            }
+            rest = &rest[end + 1..];
+        }
+        else {
+            let end = rest.find(|c| c == '$' || c == '.').unwrap_or(rest.len());
+            // A single dot is written as it is
+            let end = if end == 0 { 1 } else { end };
+            f.write_str(&rest[..end])?;
+            rest = &rest[end..];
+        }
+    }
+    Ok(())
+}
+
+fn unescape(escape: &str) -> Option<char> {
+    Some(match escape {
+        "SP" => '@',
+        "BP" => '*',
+        "RF" => '&',
+        "LT" => '<',
+        "GT" => '>',
+        "LP" => '(',
+        "RP" => ')',
+        "C" => ',',
+        _ if escape.starts_with('u') => {
+            let code = u32::from_str_radix(&escape[1..], 16).ok()?;
+            ::core::char::from_u32(code)?
+        },
+        _ => return None,
+    })
+}
@@ -0,0 +1,128 @@
+use core::fmt;
+use multiboot2::BootInformation;
+
+use memory::MemoryController;
+use memory::paging;
+
+mod demangle;
+mod symbols;
+
+use self::demangle::Demangle;
+
+// Deeper stacks are cut off, a loop in a corrupt chain must not print forever
+const MAX_FRAMES: usize = 64;
+
+// Loads the kernel symbols so backtraces show function names instead of bare addresses
+pub fn init(boot_info: &BootInformation, memory_controller: &mut MemoryController) {
+    match symbols::init(boot_info, memory_controller) {
+        Some(count) => debug!("{} kernel symbols", count),
+        None => warn!("No kernel symbol table, backtraces will only show addresses"),
+    }
+}
+
+#[inline(always)]
+pub fn frame_pointer() -> usize {
+    let rbp: usize;
+    unsafe {
+        asm!("mov $0, rbp" : "=r"(rbp) ::: "intel");
+    }
+    rbp
+}
+
+// Return addresses found by following the chain of saved frame pointers, starting with the
+// caller of the frame. Every frame starts with the caller's frame pointer followed by the return
+// address. The chain ends at the null frame pointer `long_mode_start` sets up.
+pub struct StackWalk {
+    frame_pointer: usize,
+    remaining: usize,
+}
+
+impl StackWalk {
+    pub fn new(frame_pointer: usize) -> StackWalk {
+        StackWalk {
+            frame_pointer,
+            remaining: MAX_FRAMES,
+        }
+    }
+}
+
+impl Iterator for StackWalk {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let frame = self.frame_pointer;
+        if frame == 0 || frame % 8 != 0 || self.remaining == 0 || !is_readable(frame, 16) {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let (caller_frame, return_address) = unsafe {
+            (*(frame as *const usize), *((frame + 8) as *const usize))
+        };
+        // The stack grows down, so the callers' frames are above. Anything else is corrupt.
+        self.frame_pointer = if caller_frame > frame { caller_frame } else { 0 };
+        if return_address == 0 {
+            None
+        }
+        else {
+            Some(return_address)
+        }
+    }
+}
+
+fn is_readable(address: usize, size: usize) -> bool {
+    paging::walk(address).is_mapped() && paging::walk(address + size - 1).is_mapped()
+}
+
+// An address with the function it belongs to. Return addresses point behind the call, which
+// might be the start of the next function, so they are looked up one byte earlier.
+struct Location {
+    address: usize,
+    is_return_address: bool,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lookup = if self.is_return_address { self.address - 1 } else { self.address };
+        match symbols::lookup(lookup) {
+            Some((name, start)) => write!(f, "{:#018x} {}+{:#x}", self.address, Demangle(name), self.address - start),
+            None => write!(f, "{:#018x} <unknown>", self.address),
+        }
+    }
+}
+
+fn print_frames(frame_pointer: usize, first_index: usize) {
+    let mut index = first_index;
+    for return_address in StackWalk::new(frame_pointer) {
+        kprintln!("  {:2}: {}", index, Location {
+            address: return_address,
+            is_return_address: true,
+        });
+        index += 1;
+    }
+}
+
+// Prints the function calling this one and its callers
+#[cfg(not(test))]
+#[inline(never)]
+pub fn print() {
+    kprintln!("Backtrace:");
+    // Our own frame links to the caller's
+    print_frames(frame_pointer(), 0);
+}
+
+// Prints the interrupted instruction and its callers. Must be called directly from the
+// exception handler, whose frame holds the frame pointer of the interrupted code.
+#[inline(never)]
+pub fn print_exception(instruction_pointer: usize) {
+    kprintln!("Backtrace:");
+    kprintln!("  {:2}: {}", 0, Location {
+        address: instruction_pointer,
+        is_return_address: false,
+    });
+
+    let handler_frame = unsafe { *(frame_pointer() as *const usize) };
+    if handler_frame != 0 && is_readable(handler_frame, 8) {
+        print_frames(unsafe { *(handler_frame as *const usize) }, 1);
+    }
+}
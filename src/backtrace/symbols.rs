@@ -0,0 +1,60 @@
+use core::{mem, slice, str};
+use multiboot2::BootInformation;
+use spin::Once;
+
+use elf::{self, Symbol};
+use memory::MemoryController;
+use memory::paging::entry::EntryFlags;
+
+static SYMBOLS: Once<SymbolTable> = Once::new();
+
+struct SymbolTable {
+    symbols: &'static [Symbol],
+    strings: &'static [u8],
+}
+
+impl SymbolTable {
+    fn name(&self, symbol: &Symbol) -> Option<&'static str> {
+        let start = symbol.name as usize;
+        if start >= self.strings.len() {
+            return None;
+        }
+        let length = self.strings[start..].iter().position(|&b| b == 0)?;
+        str::from_utf8(&self.strings[start..start + length]).ok()
+    }
+}
+
+// Maps the kernel's symbol table read-only. Returns the number of symbols, or `None` if the
+// boot loader didn't load one, e.g. because the kernel was stripped.
+pub fn init(boot_info: &BootInformation, memory_controller: &mut MemoryController) -> Option<usize> {
+    assert_has_not_been_called!("backtrace::symbols::init must only be called once!");
+
+    let (symtab, strtab) = elf::symbol_tables(boot_info.elf_sections_tag()?)?;
+    if symtab.addr as usize % mem::align_of::<Symbol>() != 0 {
+        return None;
+    }
+    memory_controller.identity_map_physical(symtab.addr as usize, symtab.size as usize, EntryFlags::NO_EXECUTE);
+    memory_controller.identity_map_physical(strtab.addr as usize, strtab.size as usize, EntryFlags::NO_EXECUTE);
+
+    let table = SYMBOLS.call_once(|| unsafe {
+        SymbolTable {
+            symbols: slice::from_raw_parts(symtab.addr as *const Symbol,
+                symtab.size as usize / mem::size_of::<Symbol>()),
+            strings: slice::from_raw_parts(strtab.addr as *const u8, strtab.size as usize),
+        }
+    });
+    Some(table.symbols.len())
+}
+
+// Finds the function containing `address`. Returns its mangled name and start address.
+pub fn lookup(address: usize) -> Option<(&'static str, usize)> {
+    let table = SYMBOLS.try()?;
+    let address = address as u64;
+
+    // Symbols without a size are assumed to extend to the next one
+    let symbol = table.symbols.iter()
+        .filter(|symbol| symbol.is_function() && symbol.value != 0 && symbol.value <= address)
+        .filter(|symbol| symbol.size == 0 || address < symbol.value + symbol.size)
+        .max_by_key(|symbol| symbol.value)?;
+    Some((table.name(symbol)?, symbol.value as usize))
+}
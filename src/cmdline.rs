@@ -0,0 +1,295 @@
+use core::str::Split;
+use multiboot2::BootInformation;
+use spin::Once;
+
+use io::term::console;
+use power::PanicAction;
+
+static COMMAND_LINE: Once<CommandLine> = Once::new();
+
+// Whitespace separated `key=value` options and bare flags passed by the boot loader. Quoting is
+// not supported, so values can't contain spaces.
+pub struct CommandLine {
+    raw: &'static str,
+    args: KernelArgs,
+}
+
+// The options the kernel understands
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KernelArgs {
+    // `console=vga,serial`: which console sinks get output
+    pub console: Option<&'static str>,
+    // `log=warn,memory=debug`: log filter, see `log::Filter::parse`
+    pub log: Option<&'static str>,
+    // `heap_max=16M`: how large the kernel heap may grow
+    pub heap_max: Option<usize>,
+    // `dmesg_on_panic`: print the kernel log when panicking
+    pub dmesg_on_panic: bool,
+    // `heap_report`: print the heap statistics and fragmentation map after booting
+    pub heap_report: bool,
+    // `acpi_dump`: print the ACPI tables once they are parsed
+    pub acpi_dump: bool,
+    // `panic=reboot`: what to do after a panic, see `PanicAction::parse`
+    pub panic: PanicAction,
+    // `test`: run the in-kernel tests instead of booting normally
+    pub test: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelOption {
+    pub key: &'static str,
+    pub value: Option<&'static str>,
+}
+
+pub struct Options {
+    words: Split<'static, char>,
+}
+
+impl Iterator for Options {
+    type Item = KernelOption;
+
+    fn next(&mut self) -> Option<KernelOption> {
+        let word = self.words.by_ref().find(|word| !word.is_empty())?;
+        let mut parts = word.splitn(2, '=');
+        Some(KernelOption {
+            key: parts.next().unwrap(),
+            value: parts.next(),
+        })
+    }
+}
+
+// Why an option is ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptionError {
+    Unknown,
+    MissingValue,
+    UnexpectedValue,
+    InvalidValue,
+}
+
+#[allow(dead_code)]
+impl CommandLine {
+    pub fn parse(raw: &'static str) -> CommandLine {
+        let mut command_line = CommandLine {
+            raw,
+            args: KernelArgs::default(),
+        };
+        for option in command_line.options() {
+            // Invalid options are reported once logging is set up, see `report`
+            let _ = command_line.args.apply(option);
+        }
+        command_line
+    }
+
+    pub fn raw(&self) -> &'static str {
+        self.raw
+    }
+
+    pub fn args(&self) -> &KernelArgs {
+        &self.args
+    }
+
+    pub fn options(&self) -> Options {
+        Options {
+            words: self.raw.split(' '),
+        }
+    }
+
+    // The value of the last `key=value` option with this key
+    pub fn value(&self, key: &str) -> Option<&'static str> {
+        self.options()
+            .filter(|option| option.key == key)
+            .filter_map(|option| option.value)
+            .last()
+    }
+
+    pub fn has_flag(&self, key: &str) -> bool {
+        self.options().any(|option| option.key == key && option.value.is_none())
+    }
+}
+
+impl KernelArgs {
+    fn apply(&mut self, option: KernelOption) -> Result<(), OptionError> {
+        match (option.key, option.value) {
+            ("console", Some(value)) => self.console = Some(value),
+            ("log", Some(value)) => self.log = Some(value),
+            ("heap_max", Some(value)) => {
+                self.heap_max = Some(parse_size(value).ok_or(OptionError::InvalidValue)?);
+            },
+            ("panic", Some(value)) => {
+                self.panic = PanicAction::parse(value).ok_or(OptionError::InvalidValue)?;
+            },
+            ("dmesg_on_panic", None) => self.dmesg_on_panic = true,
+            ("heap_report", None) => self.heap_report = true,
+            ("acpi_dump", None) => self.acpi_dump = true,
+            ("test", None) => self.test = true,
+            ("console", None) | ("log", None) | ("heap_max", None) | ("panic", None) => {
+                return Err(OptionError::MissingValue);
+            },
+            ("dmesg_on_panic", Some(_)) | ("heap_report", Some(_)) | ("acpi_dump", Some(_)) |
+            ("test", Some(_)) => {
+                return Err(OptionError::UnexpectedValue);
+            },
+            _ => return Err(OptionError::Unknown),
+        }
+        Ok(())
+    }
+}
+
+// Parses a byte count with an optional `K`, `M` or `G` suffix
+fn parse_size(s: &str) -> Option<usize> {
+    let (digits, shift) = match s.as_bytes().last() {
+        Some(&b'K') | Some(&b'k') => (&s[..s.len() - 1], 10),
+        Some(&b'M') | Some(&b'm') => (&s[..s.len() - 1], 20),
+        Some(&b'G') | Some(&b'g') => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let value: usize = digits.parse().ok()?;
+    value.checked_mul(1 << shift)
+}
+
+// Parses the command line the boot loader passed. An absent command line is treated as empty.
+pub fn init(boot_info: &BootInformation) -> &'static CommandLine {
+    assert_has_not_been_called!("cmdline::init must only be called once!");
+
+    let raw = boot_info.command_line_tag()
+        .map(|tag| tag.command_line().trim_right_matches('\0'))
+        .unwrap_or("");
+    // The command line lives in the multiboot information, which stays mapped
+    let raw: &'static str = unsafe { &*(raw as *const str) };
+    COMMAND_LINE.call_once(|| CommandLine::parse(raw))
+}
+
+// Returns the command line, or `None` before `init`
+#[allow(dead_code)]
+pub fn command_line() -> Option<&'static CommandLine> {
+    COMMAND_LINE.try()
+}
+
+// The parsed options, or the defaults before `init`
+pub fn args() -> KernelArgs {
+    command_line().map(|command_line| *command_line.args()).unwrap_or_default()
+}
+
+// Applies the options that configure the console and logging. Done as early as possible so
+// that everything after it is logged as asked.
+pub fn apply_early(command_line: &CommandLine) {
+    let args = command_line.args();
+    if let Some(names) = args.console {
+        if let Err(name) = console::select(names) {
+            warn!("Unknown console {:?}, keeping the default consoles", name);
+        }
+    }
+    if let Some(spec) = args.log {
+        if let Err(error) = ::log::set_filter_spec(spec) {
+            warn!("Ignoring log={}: {}", spec, error);
+        }
+    }
+    ::log::set_dump_on_panic(args.dmesg_on_panic);
+}
+
+// Logs the command line and warns about every option that was ignored
+pub fn report(command_line: &CommandLine) {
+    info!("Command line: {:?}", command_line.raw());
+    let mut args = KernelArgs::default();
+    for option in command_line.options() {
+        let problem = match args.apply(option) {
+            Ok(()) => continue,
+            Err(OptionError::Unknown) => "unknown option",
+            Err(OptionError::MissingValue) => "missing value",
+            Err(OptionError::UnexpectedValue) => "takes no value",
+            Err(OptionError::InvalidValue) => "invalid value",
+        };
+        match option.value {
+            Some(value) => warn!("Ignoring {}={}: {}", option.key, value, problem),
+            None => warn!("Ignoring {}: {}", option.key, problem),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::usize;
+
+    use power::PanicAction;
+    use super::{parse_size, CommandLine, KernelArgs, KernelOption, OptionError};
+
+    fn apply(key: &'static str, value: Option<&'static str>) -> Result<KernelArgs, OptionError> {
+        let mut args = KernelArgs::default();
+        args.apply(KernelOption { key, value }).map(|()| args)
+    }
+
+    #[test]
+    fn sizes_take_binary_suffixes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("16K"), Some(16 << 10));
+        assert_eq!(parse_size("16k"), Some(16 << 10));
+        assert_eq!(parse_size("64M"), Some(64 << 20));
+        assert_eq!(parse_size("2G"), Some(2 << 30));
+        assert_eq!(parse_size("0M"), Some(0));
+    }
+
+    #[test]
+    fn malformed_sizes_are_rejected() {
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("12X"), None);
+        assert_eq!(parse_size("-1K"), None);
+        assert_eq!(parse_size("1.5M"), None);
+    }
+
+    #[test]
+    fn overflowing_sizes_are_rejected() {
+        assert_eq!(parse_size(&format!("{}", usize::MAX)), Some(usize::MAX));
+        assert_eq!(parse_size(&format!("{}0", usize::MAX)), None);
+        assert_eq!(parse_size(&format!("{}K", usize::MAX / 1024)), Some(usize::MAX / 1024 * 1024));
+        assert_eq!(parse_size(&format!("{}K", usize::MAX / 1024 + 1)), None);
+        assert_eq!(parse_size(&format!("{}G", usize::MAX >> 29)), None);
+    }
+
+    #[test]
+    fn options_are_checked() {
+        assert_eq!(apply("heap_max", Some("1M")).unwrap().heap_max, Some(1 << 20));
+        assert_eq!(apply("panic", Some("exit:3")).unwrap().panic, PanicAction::Exit(3));
+        assert!(apply("test", None).unwrap().test);
+
+        assert_eq!(apply("frobnicate", None).err(), Some(OptionError::Unknown));
+        assert_eq!(apply("frobnicate", Some("1")).err(), Some(OptionError::Unknown));
+        assert_eq!(apply("heap_max", None).err(), Some(OptionError::MissingValue));
+        assert_eq!(apply("test", Some("1")).err(), Some(OptionError::UnexpectedValue));
+        assert_eq!(apply("heap_max", Some("lots")).err(), Some(OptionError::InvalidValue));
+        assert_eq!(apply("panic", Some("explode")).err(), Some(OptionError::InvalidValue));
+    }
+
+    #[test]
+    fn invalid_options_are_skipped() {
+        let command_line = CommandLine::parse("frobnicate heap_max=lots test=1 console=serial");
+        let args = command_line.args();
+        assert_eq!(args.heap_max, None);
+        assert!(!args.test);
+        assert_eq!(args.console, Some("serial"));
+    }
+
+    #[test]
+    fn the_last_repeated_option_wins() {
+        let command_line = CommandLine::parse("heap_max=1M  log=info heap_max=2M log=debug");
+        assert_eq!(command_line.args().heap_max, Some(2 << 20));
+        assert_eq!(command_line.args().log, Some("debug"));
+        assert_eq!(command_line.value("log"), Some("debug"));
+        // An invalid repetition keeps the earlier value
+        let command_line = CommandLine::parse("heap_max=1M heap_max=lots");
+        assert_eq!(command_line.args().heap_max, Some(1 << 20));
+    }
+
+    #[test]
+    fn options_are_split_on_spaces() {
+        let command_line = CommandLine::parse(" test  log=a=b ");
+        let options: Vec<KernelOption> = command_line.options().collect();
+        assert_eq!(options, [
+            KernelOption { key: "test", value: None },
+            KernelOption { key: "log", value: Some("a=b") },
+        ]);
+        assert!(command_line.has_flag("test"));
+        assert!(!command_line.has_flag("log"));
+    }
+}
@@ -0,0 +1,73 @@
+use core::ptr;
+use multiboot2::ElfSectionsTag;
+
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const STT_FUNC: u8 = 2;
+
+// Offsets into the multiboot2 ELF sections tag
+const TAG_ENTRY_SIZE: usize = 12;
+const TAG_FIRST_SECTION: usize = 20;
+
+// ELF64 section header. The one in multiboot2 hides the type and link fields.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub typ: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entry_size: u64,
+}
+
+// ELF64 symbol table entry
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Symbol {
+    pub name: u32,
+    pub info: u8,
+    pub other: u8,
+    pub section_index: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl Symbol {
+    pub fn is_function(&self) -> bool {
+        self.info & 0xf == STT_FUNC
+    }
+}
+
+// Returns the header of section `index`. Unlike `ElfSectionsTag::sections` this doesn't skip
+// unused sections, so the index matches the ones other headers refer to.
+pub fn section_header(tag: &ElfSectionsTag, index: u32) -> Option<SectionHeader> {
+    if index >= tag.number_of_sections {
+        return None;
+    }
+    // The headers start at a 4 byte boundary, so their 64 bit fields can be misaligned
+    let base = tag as *const ElfSectionsTag as usize;
+    unsafe {
+        let entry_size = ptr::read_unaligned((base + TAG_ENTRY_SIZE) as *const u32) as usize;
+        let header = base + TAG_FIRST_SECTION + index as usize * entry_size;
+        Some(ptr::read_unaligned(header as *const SectionHeader))
+    }
+}
+
+// The symbol table and the string table with its names, if the boot loader loaded them
+pub fn symbol_tables(tag: &ElfSectionsTag) -> Option<(SectionHeader, SectionHeader)> {
+    let symtab = (0..tag.number_of_sections)
+        .filter_map(|index| section_header(tag, index))
+        .find(|header| header.typ == SHT_SYMTAB && header.addr != 0 && header.size != 0)?;
+    let strtab = section_header(tag, symtab.link)?;
+    if strtab.typ == SHT_STRTAB && strtab.addr != 0 && strtab.size != 0 {
+        Some((symtab, strtab))
+    }
+    else {
+        None
+    }
+}
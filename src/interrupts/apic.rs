@@ -1,14 +1,172 @@
 #![allow(dead_code)]
 
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Once;
+
+use x86_64::instructions::{rdmsr, wrmsr};
+use x86_64::registers::msr::IA32_APIC_BASE;
+use x86_64::structures::idt::ExceptionStackFrame;
+
 use io::{UnsafePort};
+use memory::{MemoryController, PAGE_SIZE};
+use time;
 
+// Vector the local APIC uses for spurious interrupts. Its low four bits must be set on older CPUs
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+// Vector to start the timer on to get the handler set with `set_timer_handler` called
+pub const TIMER_VECTOR: u8 = 0xf0;
 
-pub struct Apic {
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+// x2APIC registers are MSRs starting here, one for every 16 byte MMIO register
+const X2APIC_MSR_BASE: u32 = 0x800;
 
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+#[derive(Clone, Copy)]
+#[repr(usize)]
+// Offsets from the MMIO base
+enum Register {
+    Id = 0x20,
+    Version = 0x30,
+    TaskPriority = 0x80,
+    EndOfInterrupt = 0xb0,
+    SpuriousInterruptVector = 0xf0,
+    ErrorStatus = 0x280,
+    LvtTimer = 0x320,
+    LvtLint0 = 0x350,
+    LvtLint1 = 0x360,
+    LvtError = 0x370,
+    TimerInitialCount = 0x380,
+    TimerCurrentCount = 0x390,
+    TimerDivideConfiguration = 0x3e0,
 }
 
-impl Apic {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TimerMode {
+    OneShot = 0 << 17,
+    Periodic = 1 << 17,
+}
 
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+static TIMER_HANDLER: Once<fn()> = Once::new();
+
+pub struct LocalApic {
+    // Virtual address of the register page, `None` in x2APIC mode where registers are MSRs
+    base: Option<usize>,
+    // Zero until `calibrate_timer` ran
+    timer_ticks_per_ms: AtomicUsize,
+}
+
+impl LocalApic {
+    fn read(&self, register: Register) -> u32 {
+        match self.base {
+            Some(base) => unsafe { ptr::read_volatile((base + register as usize) as *const u32) },
+            None => rdmsr(X2APIC_MSR_BASE + (register as usize >> 4) as u32) as u32,
+        }
+    }
+
+    fn write(&self, register: Register, val: u32) {
+        match self.base {
+            Some(base) => unsafe { ptr::write_volatile((base + register as usize) as *mut u32, val) },
+            None => unsafe { wrmsr(X2APIC_MSR_BASE + (register as usize >> 4) as u32, val as u64) },
+        }
+    }
+
+    pub fn is_x2apic(&self) -> bool {
+        self.base.is_none()
+    }
+
+    pub fn id(&self) -> u32 {
+        match self.base {
+            Some(_) => self.read(Register::Id) >> 24,
+            None => self.read(Register::Id),
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.read(Register::Version) as u8
+    }
+
+    pub fn end_of_interrupt(&self) {
+        self.write(Register::EndOfInterrupt, 0);
+    }
+
+    fn enable(&self) {
+        // Accept all interrupts
+        self.write(Register::TaskPriority, 0);
+        self.write(Register::LvtTimer, LVT_MASKED);
+        self.write(Register::LvtError, LVT_MASKED);
+        self.write(Register::SpuriousInterruptVector, SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+
+    // Timer ticks per millisecond with the divider the kernel uses
+    pub fn timer_ticks_per_ms(&self) -> u32 {
+        self.timer_ticks_per_ms.load(Ordering::Relaxed) as u32
+    }
+
+    // Fires `vector` once after `microseconds` or every `microseconds` depending on `mode`
+    pub fn start_timer(&self, mode: TimerMode, vector: u8, microseconds: u64) {
+        let ticks = self.timer_ticks_per_ms() as u64 * microseconds / 1000;
+        assert!(ticks > 0 && ticks <= u32::max_value() as u64,
+            "can not program the APIC timer for {}us", microseconds);
+
+        self.write(Register::TimerDivideConfiguration, TIMER_DIVIDE_BY_16);
+        self.write(Register::LvtTimer, mode as u32 | vector as u32);
+        self.write(Register::TimerInitialCount, ticks as u32);
+    }
+
+    pub fn stop_timer(&self) {
+        self.write(Register::LvtTimer, LVT_MASKED);
+        self.write(Register::TimerInitialCount, 0);
+    }
+
+    // Measures the timer frequency, see `time::calibrate`. The timer can't be started before.
+    pub fn calibrate_timer(&self) {
+        self.write(Register::TimerDivideConfiguration, TIMER_DIVIDE_BY_16);
+        self.write(Register::LvtTimer, LVT_MASKED);
+        self.write(Register::TimerInitialCount, u32::max_value());
+        let ticks_per_ms = time::calibrate(||
+            (u32::max_value() - self.read(Register::TimerCurrentCount)) as u64);
+        self.write(Register::TimerInitialCount, 0);
+        self.timer_ticks_per_ms.store(ticks_per_ms as usize, Ordering::Relaxed);
+    }
+}
+
+// Returns the local APIC of this CPU. Panics if `init` was not called yet.
+pub fn local_apic() -> &'static LocalApic {
+    LOCAL_APIC.try().expect("The local APIC has not been initialized!")
+}
+
+// Signals the end of the current interrupt to the local APIC
+pub fn end_of_interrupt() {
+    if let Some(apic) = LOCAL_APIC.try() {
+        apic.end_of_interrupt();
+    }
+}
+
+// Calls `handler` for every interrupt on `TIMER_VECTOR`. Only one handler can be set.
+pub fn set_timer_handler(handler: fn()) {
+    assert!(TIMER_HANDLER.try().is_none(), "the APIC timer already has a handler");
+    TIMER_HANDLER.call_once(|| handler);
+}
+
+// Unlike `irq` handlers, the timer handler runs after the EOI was sent. It may switch to another
+// thread, which would otherwise run with the timer interrupt still in service.
+pub extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: &mut ExceptionStackFrame) {
+    end_of_interrupt();
+    if let Some(&handler) = TIMER_HANDLER.try() {
+        handler();
+    }
+}
+
+pub extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: &mut ExceptionStackFrame) {
+    // Spurious interrupts must not be acknowledged with an EOI
 }
 
 fn apic_enabled() -> bool {
@@ -21,12 +179,45 @@ fn apic_enabled() -> bool {
     }
 }
 
-pub unsafe fn init() {
+fn x2apic_supported() -> bool {
+    use raw_cpuid::CpuId;
+    let cpu_id = CpuId::new();
+
+    match cpu_id.get_feature_info() {
+        Some(vf) => vf.has_x2apic(),
+        None => false,
+    }
+}
+
+pub unsafe fn init(memory_controller: &mut MemoryController) {
     assert_has_not_been_called!("Must only initialize the APIC once!");
     disable_pic();
     if !apic_enabled() {
         panic!("The kernel required APIC to operate!");
     }
+
+    let mut apic_base = rdmsr(IA32_APIC_BASE) | APIC_BASE_ENABLE;
+    wrmsr(IA32_APIC_BASE, apic_base);
+
+    let base = if x2apic_supported() {
+        // Switching to x2APIC mode is only allowed once the xAPIC is enabled
+        apic_base |= APIC_BASE_X2APIC;
+        wrmsr(IA32_APIC_BASE, apic_base);
+        None
+    }
+    else {
+        let address = (apic_base & APIC_BASE_ADDRESS_MASK) as usize;
+        memory_controller.identity_map_mmio(address, PAGE_SIZE);
+        Some(address)
+    };
+
+    let apic = LocalApic {
+        base,
+        timer_ticks_per_ms: AtomicUsize::new(0),
+    };
+    apic.enable();
+
+    LOCAL_APIC.call_once(|| apic);
 }
 
 unsafe fn disable_pic() {
@@ -39,4 +230,4 @@ unsafe fn disable_pic() {
 
     pic2_port.write(PIC_DISABLE_COMMAND);
     pic1_port.write(PIC_DISABLE_COMMAND);
-}
\ No newline at end of file
+}
@@ -0,0 +1,139 @@
+use x86_64::structures::idt::{Idt, ExceptionStackFrame};
+
+use backtrace;
+use io::term::console;
+use memory::paging;
+use power;
+
+// Number of bytes printed from the faulting instruction. No x86 instruction is longer.
+const INSTRUCTION_BYTES: usize = 15;
+
+// Exceptions whose error code is a segment selector index
+const SELECTOR_ERROR_CODES: [u8; 4] = [10, 11, 12, 13];
+
+// Installs a handler for every architectural exception except the page fault and double
+// fault, which need extra setup
+pub fn install(idt: &mut Idt) {
+    idt.divide_by_zero.set_handler_fn(divide_by_zero_handler);
+    idt.debug.set_handler_fn(debug_handler);
+    idt.non_maskable_interrupt.set_handler_fn(non_maskable_interrupt_handler);
+    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded.set_handler_fn(bound_range_exceeded_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available.set_handler_fn(device_not_available_handler);
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present.set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
+    idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    idt.machine_check.set_handler_fn(machine_check_handler);
+    idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
+    idt.virtualization.set_handler_fn(virtualization_handler);
+    idt.security_exception.set_handler_fn(security_exception_handler);
+}
+
+// Prints the same dump for every exception: the stack frame, the decoded error code if there
+// is one and the bytes of the faulting instruction. Fatal exceptions take over the console
+// first, they may have interrupted a sink while it was locked.
+pub fn dump(name: &str, vector: u8, stack_frame: &ExceptionStackFrame, error_code: Option<u64>) {
+    match error_code {
+        Some(code) => kprintln!("Exception: {} (vector {}) Code {:#x}", name, vector, code),
+        None => kprintln!("Exception: {} (vector {})", name, vector),
+    }
+    if let Some(code) = error_code {
+        if SELECTOR_ERROR_CODES.contains(&vector) {
+            print_selector_error_code(code);
+        }
+    }
+
+    let rip = stack_frame.instruction_pointer.0;
+    kprintln!("  rip {:#018x}  cs {:#06x}  rflags {:#010x}",
+        rip, stack_frame.code_segment, stack_frame.cpu_flags);
+    kprintln!("  rsp {:#018x}  ss {:#06x}",
+        stack_frame.stack_pointer.0, stack_frame.stack_segment);
+    print_instruction_bytes(rip);
+}
+
+fn print_selector_error_code(code: u64) {
+    if code == 0 {
+        kprintln!("  not caused by a segment selector");
+        return;
+    }
+
+    let table = match (code >> 1) & 0b11 {
+        0 => "GDT",
+        2 => "LDT",
+        _ => "IDT",
+    };
+    kprintln!("  selector {}[{}]{}", table, (code >> 3) & 0x1fff,
+        if code & 1 != 0 { ", external event" } else { "" });
+}
+
+fn print_instruction_bytes(rip: usize) {
+    // Reading an unmapped instruction pointer would fault again
+    if !paging::walk(rip).is_mapped() || !paging::walk(rip + INSTRUCTION_BYTES - 1).is_mapped() {
+        kprintln!("  instruction bytes at rip are not mapped");
+        return;
+    }
+
+    kprint!("  instruction bytes:");
+    for i in 0..INSTRUCTION_BYTES {
+        let byte = unsafe { *((rip + i) as *const u8) };
+        kprint!(" {:02x}", byte);
+    }
+    kprintln!("");
+}
+
+macro_rules! exception_handler {
+    ($handler:ident, $name:expr, $vector:expr) => {
+        extern "x86-interrupt" fn $handler(stack_frame: &mut ExceptionStackFrame) {
+            unsafe { console::take_over() };
+            dump($name, $vector, stack_frame, None);
+            backtrace::print_exception(stack_frame.instruction_pointer.0);
+            power::after_panic()
+        }
+    };
+    ($handler:ident, $name:expr, $vector:expr, error_code) => {
+        extern "x86-interrupt" fn $handler(stack_frame: &mut ExceptionStackFrame, error_code: u64) {
+            unsafe { console::take_over() };
+            dump($name, $vector, stack_frame, Some(error_code));
+            backtrace::print_exception(stack_frame.instruction_pointer.0);
+            power::after_panic()
+        }
+    };
+}
+
+exception_handler!(divide_by_zero_handler, "DIVIDE_BY_ZERO", 0);
+exception_handler!(non_maskable_interrupt_handler, "NON_MASKABLE_INTERRUPT", 2);
+exception_handler!(overflow_handler, "OVERFLOW", 4);
+exception_handler!(bound_range_exceeded_handler, "BOUND_RANGE_EXCEEDED", 5);
+exception_handler!(invalid_opcode_handler, "INVALID_OPCODE", 6);
+exception_handler!(device_not_available_handler, "DEVICE_NOT_AVAILABLE", 7);
+exception_handler!(invalid_tss_handler, "INVALID_TSS", 10, error_code);
+exception_handler!(segment_not_present_handler, "SEGMENT_NOT_PRESENT", 11, error_code);
+exception_handler!(stack_segment_fault_handler, "STACK_SEGMENT_FAULT", 12, error_code);
+exception_handler!(general_protection_fault_handler, "GENERAL_PROTECTION_FAULT", 13, error_code);
+exception_handler!(x87_floating_point_handler, "X87_FLOATING_POINT", 16);
+exception_handler!(alignment_check_handler, "ALIGNMENT_CHECK", 17, error_code);
+exception_handler!(machine_check_handler, "MACHINE_CHECK", 18);
+exception_handler!(simd_floating_point_handler, "SIMD_FLOATING_POINT", 19);
+exception_handler!(virtualization_handler, "VIRTUALIZATION", 20);
+exception_handler!(security_exception_handler, "SECURITY_EXCEPTION", 30, error_code);
+
+// Debug and breakpoint exceptions are traps, so execution can just continue
+extern "x86-interrupt" fn debug_handler(stack_frame: &mut ExceptionStackFrame) {
+    dump("DEBUG", 1, stack_frame, None);
+}
+
+extern "x86-interrupt" fn breakpoint_handler(stack_frame: &mut ExceptionStackFrame) {
+    dump("BREAK_POINT", 3, stack_frame, None);
+}
+
+pub extern "x86-interrupt" fn double_fault_handler(stack_frame: &mut ExceptionStackFrame, error_code: u64) {
+    unsafe { console::take_over() };
+    dump("DOUBLE_FAULT", 8, stack_frame, Some(error_code));
+    backtrace::print_exception(stack_frame.instruction_pointer.0);
+    power::after_panic()
+}
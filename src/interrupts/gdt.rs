@@ -98,3 +98,56 @@ impl Descriptor {
         Descriptor::SystemSegment(low, high)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mem::size_of;
+    use bit_field::BitField;
+
+    lazy_static! {
+        // Descriptors need a TSS that lives forever
+        static ref TSS: TaskStateSegment = TaskStateSegment::new();
+    }
+
+    #[test]
+    fn kernel_code_segment_is_present_64_bit_code() {
+        match Descriptor::kernel_code_segment() {
+            Descriptor::UserSegment(value) => assert_eq!(value, 0x0020_9800_0000_0000),
+            Descriptor::SystemSegment(..) => panic!("the code segment is a user segment"),
+        }
+    }
+
+    #[test]
+    fn tss_segment_packs_base_limit_and_type() {
+        let base = &*TSS as *const _ as u64;
+
+        let (low, high) = match Descriptor::tss_segment(&TSS) {
+            Descriptor::SystemSegment(low, high) => (low, high),
+            Descriptor::UserSegment(_) => panic!("the TSS segment is a system segment"),
+        };
+        assert_eq!(low.get_bits(0..16), (size_of::<TaskStateSegment>() - 1) as u64);
+        assert_eq!(low.get_bits(16..40) | low.get_bits(56..64) << 24 | high.get_bits(0..32) << 32, base);
+        assert_eq!(low.get_bits(40..44), 0b1001);
+        assert!(low.get_bit(47), "not present");
+        assert_eq!(high.get_bits(32..64), 0);
+    }
+
+    #[test]
+    fn system_segments_take_two_entries() {
+        let mut gdt = Gdt::new();
+
+        assert_eq!(gdt.add_entry(Descriptor::kernel_code_segment()).0, 1 << 3);
+        assert_eq!(gdt.add_entry(Descriptor::tss_segment(&TSS)).0, 2 << 3);
+        assert_eq!(gdt.add_entry(Descriptor::kernel_code_segment()).0, 4 << 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "GDT is full!")]
+    fn full_gdt_panics() {
+        let mut gdt = Gdt::new();
+        for _ in 0..8 {
+            gdt.add_entry(Descriptor::kernel_code_segment());
+        }
+    }
+}
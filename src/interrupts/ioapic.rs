@@ -0,0 +1,209 @@
+#![allow(dead_code)]
+
+use core::ptr;
+use spin::Mutex;
+
+use memory::MemoryController;
+use super::{apic, irq, without_interrupts};
+use super::irq::IrqHandler;
+
+// Where the I/O APIC is on every PC unless the firmware says otherwise
+pub const DEFAULT_IO_APIC_ADDRESS: usize = 0xfec0_0000;
+
+const MAX_IO_APICS: usize = 4;
+const ISA_IRQ_COUNT: usize = 16;
+
+// Register select and data window, relative to the base address
+const IO_REGISTER_SELECT: usize = 0x00;
+const IO_WINDOW: usize = 0x10;
+
+const REGISTER_ID: u32 = 0x00;
+const REGISTER_VERSION: u32 = 0x01;
+const REGISTER_REDIRECTION_TABLE: u32 = 0x10;
+
+// Commonly used ISA IRQs
+pub const IRQ_PIT: u8 = 0;
+pub const IRQ_KEYBOARD: u8 = 1;
+pub const IRQ_COM2: u8 = 3;
+pub const IRQ_COM1: u8 = 4;
+pub const IRQ_RTC: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+
+// An entry of the redirection table, which decides how a pin of the I/O APIC is delivered
+#[derive(Debug, Clone, Copy)]
+pub struct RedirectionEntry {
+    pub vector: u8,
+    // APIC ID of the CPU that receives the interrupt
+    pub destination: u8,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+    pub masked: bool,
+}
+
+impl RedirectionEntry {
+    fn to_bits(&self) -> u64 {
+        // Delivery mode fixed and physical destination mode are both zero
+        let mut bits = self.vector as u64;
+        if self.polarity == Polarity::ActiveLow {
+            bits |= 1 << 13;
+        }
+        if self.trigger_mode == TriggerMode::Level {
+            bits |= 1 << 15;
+        }
+        if self.masked {
+            bits |= 1 << 16;
+        }
+        bits | (self.destination as u64) << 56
+    }
+}
+
+// Tells that an ISA IRQ is wired to a different global system interrupt or with different
+// polarity and trigger mode than the ISA defaults
+#[derive(Debug, Clone, Copy)]
+pub struct SourceOverride {
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+pub struct IoApic {
+    id: u8,
+    base: usize,
+    // First global system interrupt handled by this I/O APIC
+    gsi_base: u32,
+    entry_count: u32,
+}
+
+impl IoApic {
+    unsafe fn new(base: usize, gsi_base: u32) -> IoApic {
+        let mut io_apic = IoApic {
+            id: 0,
+            base,
+            gsi_base,
+            entry_count: 0,
+        };
+        io_apic.id = (io_apic.read(REGISTER_ID) >> 24) as u8 & 0xf;
+        io_apic.entry_count = ((io_apic.read(REGISTER_VERSION) >> 16) & 0xff) + 1;
+        io_apic
+    }
+
+    fn read(&self, register: u32) -> u32 {
+        unsafe {
+            ptr::write_volatile((self.base + IO_REGISTER_SELECT) as *mut u32, register);
+            ptr::read_volatile((self.base + IO_WINDOW) as *const u32)
+        }
+    }
+
+    fn write(&mut self, register: u32, val: u32) {
+        unsafe {
+            ptr::write_volatile((self.base + IO_REGISTER_SELECT) as *mut u32, register);
+            ptr::write_volatile((self.base + IO_WINDOW) as *mut u32, val);
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.entry_count
+    }
+
+    pub fn set_entry(&mut self, gsi: u32, entry: RedirectionEntry) {
+        let register = REGISTER_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        let bits = entry.to_bits();
+        // Mask the pin while the entry is half written
+        self.write(register, 1 << 16);
+        self.write(register + 1, (bits >> 32) as u32);
+        self.write(register, bits as u32);
+    }
+
+    pub fn set_masked(&mut self, gsi: u32, masked: bool) {
+        let register = REGISTER_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        let low = self.read(register);
+        self.write(register, if masked { low | 1 << 16 } else { low & !(1 << 16) });
+    }
+
+    fn mask_all(&mut self) {
+        for gsi in self.gsi_base..self.gsi_base + self.entry_count {
+            self.set_masked(gsi, true);
+        }
+    }
+}
+
+static IO_APICS: Mutex<[Option<IoApic>; MAX_IO_APICS]> = Mutex::new([None, None, None, None]);
+static OVERRIDES: Mutex<[Option<SourceOverride>; ISA_IRQ_COUNT]> = Mutex::new([None; ISA_IRQ_COUNT]);
+
+// Maps the I/O APIC at `address` and masks all of its pins
+pub fn add_io_apic(memory_controller: &mut MemoryController, address: usize, gsi_base: u32) {
+    memory_controller.identity_map_mmio(address, IO_WINDOW + 4);
+
+    let mut io_apic = unsafe { IoApic::new(address, gsi_base) };
+    io_apic.mask_all();
+
+    let mut io_apics = IO_APICS.lock();
+    let slot = io_apics.iter_mut().find(|slot| slot.is_none())
+        .expect("Too many I/O APICs!");
+    *slot = Some(io_apic);
+}
+
+// Records that ISA `irq` is connected differently than the ISA defaults
+pub fn add_source_override(irq: u8, source_override: SourceOverride) {
+    assert!((irq as usize) < ISA_IRQ_COUNT, "{} is not an ISA IRQ", irq);
+    OVERRIDES.lock()[irq as usize] = Some(source_override);
+}
+
+// Global system interrupt, polarity and trigger mode of an ISA IRQ
+fn resolve_isa_irq(irq: u8) -> SourceOverride {
+    assert!((irq as usize) < ISA_IRQ_COUNT, "{} is not an ISA IRQ", irq);
+    OVERRIDES.lock()[irq as usize].unwrap_or(SourceOverride {
+        gsi: irq as u32,
+        polarity: Polarity::ActiveHigh,
+        trigger_mode: TriggerMode::Edge,
+    })
+}
+
+fn with_io_apic<F: FnOnce(&mut IoApic)>(gsi: u32, f: F) {
+    let mut io_apics = IO_APICS.lock();
+    let io_apic = io_apics.iter_mut()
+        .filter_map(|io_apic| io_apic.as_mut())
+        .find(|io_apic| io_apic.handles(gsi))
+        .expect("No I/O APIC handles this interrupt!");
+    f(io_apic);
+}
+
+// Delivers ISA `irq` to `handler` on `vector` on this CPU
+pub fn route_irq(irq: u8, vector: u8, handler: IrqHandler) {
+    let source = resolve_isa_irq(irq);
+    irq::register_handler(vector, handler);
+
+    let entry = RedirectionEntry {
+        vector,
+        destination: apic::local_apic().id() as u8,
+        polarity: source.polarity,
+        trigger_mode: source.trigger_mode,
+        masked: false,
+    };
+    without_interrupts(|| with_io_apic(source.gsi, |io_apic| io_apic.set_entry(source.gsi, entry)));
+}
+
+pub fn mask_irq(irq: u8) {
+    let gsi = resolve_isa_irq(irq).gsi;
+    without_interrupts(|| with_io_apic(gsi, |io_apic| io_apic.set_masked(gsi, true)));
+}
+
+pub fn unmask_irq(irq: u8) {
+    let gsi = resolve_isa_irq(irq).gsi;
+    without_interrupts(|| with_io_apic(gsi, |io_apic| io_apic.set_masked(gsi, false)));
+}
@@ -0,0 +1,75 @@
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use x86_64::structures::idt::{Idt, ExceptionStackFrame};
+
+use super::apic;
+
+// Vectors `FIRST_VECTOR..FIRST_VECTOR + VECTOR_COUNT` call the handler registered with
+// `register_handler`. The IDT is built once, so every vector gets a stub that dispatches to
+// whatever handler is registered at the time.
+pub const FIRST_VECTOR: u8 = 32;
+pub const VECTOR_COUNT: usize = 32;
+
+// Called for an interrupt on the vector it was registered for. The local APIC is sent an EOI
+// after it returns.
+pub type IrqHandler = fn();
+
+pub fn register_handler(vector: u8, handler: IrqHandler) {
+    let previous = HANDLERS[vector_index(vector)].compare_and_swap(0, handler as usize, Ordering::SeqCst);
+    assert!(previous == 0, "vector {} already has a handler", vector);
+}
+
+pub fn unregister_handler(vector: u8) {
+    HANDLERS[vector_index(vector)].store(0, Ordering::SeqCst);
+}
+
+fn vector_index(vector: u8) -> usize {
+    assert!(vector >= FIRST_VECTOR && ((vector - FIRST_VECTOR) as usize) < VECTOR_COUNT,
+        "vector {} can not have a dynamic handler", vector);
+    (vector - FIRST_VECTOR) as usize
+}
+
+fn dispatch(vector: u8) {
+    let handler = HANDLERS[(vector - FIRST_VECTOR) as usize].load(Ordering::SeqCst);
+    if handler != 0 {
+        let handler: IrqHandler = unsafe { mem::transmute(handler) };
+        handler();
+    }
+    apic::end_of_interrupt();
+}
+
+macro_rules! empty_handler {
+    ($vector:expr) => (AtomicUsize::new(0))
+}
+
+macro_rules! irq_stubs {
+    ($($stub:ident => $vector:expr),*) => {
+        // The address of each vector's `IrqHandler`, 0 if it has none. Atomic instead of locked,
+        // so `dispatch` never has to drop an interrupt because a registration holds the table.
+        static HANDLERS: [AtomicUsize; VECTOR_COUNT] = [$(empty_handler!($vector)),*];
+
+        $(
+            extern "x86-interrupt" fn $stub(_stack_frame: &mut ExceptionStackFrame) {
+                dispatch($vector);
+            }
+        )*
+
+        pub fn install(idt: &mut Idt) {
+            $(
+                idt[$vector].set_handler_fn($stub);
+            )*
+        }
+    };
+}
+
+irq_stubs!(
+    irq_32 => 32, irq_33 => 33, irq_34 => 34, irq_35 => 35,
+    irq_36 => 36, irq_37 => 37, irq_38 => 38, irq_39 => 39,
+    irq_40 => 40, irq_41 => 41, irq_42 => 42, irq_43 => 43,
+    irq_44 => 44, irq_45 => 45, irq_46 => 46, irq_47 => 47,
+    irq_48 => 48, irq_49 => 49, irq_50 => 50, irq_51 => 51,
+    irq_52 => 52, irq_53 => 53, irq_54 => 54, irq_55 => 55,
+    irq_56 => 56, irq_57 => 57, irq_58 => 58, irq_59 => 59,
+    irq_60 => 60, irq_61 => 61, irq_62 => 62, irq_63 => 63
+);
@@ -0,0 +1,23 @@
+use x86_64::instructions::interrupts;
+use x86_64::registers::flags::{self, IF};
+
+use super::without_interrupts;
+
+kernel_test! {
+    fn without_interrupts_restores_the_interrupt_flag() {
+        assert!(flags::flags().contains(IF));
+        without_interrupts(|| {
+            assert!(!flags::flags().contains(IF));
+            // Nesting must not enable interrupts early
+            without_interrupts(|| {});
+            assert!(!flags::flags().contains(IF));
+        });
+        assert!(flags::flags().contains(IF));
+    }
+}
+
+kernel_test! {
+    fn breakpoint_returns() {
+        interrupts::int3();
+    }
+}
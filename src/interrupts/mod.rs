@@ -1,20 +1,32 @@
 use spin::Once;
 
-use x86_64::structures::idt::{Idt, ExceptionStackFrame};
+use x86_64::structures::idt::Idt;
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtualAddress;
 
+use acpi;
 use memory::MemoryController;
 
 pub mod apic;
+mod exceptions;
 mod gdt;
+pub mod ioapic;
+pub mod irq;
+mod kernel_tests;
+mod page_fault;
+
+pub use self::page_fault::register_hook as register_page_fault_hook;
 
 lazy_static! {
     static ref IDT: Idt = {
         let mut idt = Idt::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
+        exceptions::install(&mut idt);
+        irq::install(&mut idt);
+        idt[apic::TIMER_VECTOR as usize].set_handler_fn(apic::timer_interrupt_handler);
+        idt[apic::SPURIOUS_VECTOR as usize].set_handler_fn(apic::spurious_interrupt_handler);
+        idt.page_fault.set_handler_fn(page_fault::page_fault_handler);
         unsafe {
-            idt.double_fault.set_handler_fn(double_fault_handler)
+            idt.double_fault.set_handler_fn(exceptions::double_fault_handler)
                 .set_stack_index(DOUBLE_FAULT_IST_INDEX as u16);
         }
 
@@ -31,6 +43,7 @@ pub fn init(memory_controller: &mut MemoryController) {
     use x86_64::structures::gdt::SegmentSelector;
     use x86_64::instructions::segmentation::set_cs;
     use x86_64::instructions::tables::load_tss;
+    use x86_64::instructions::interrupts;
 
     assert_has_not_been_called!("Initialize interrupts only once!");
 
@@ -58,18 +71,65 @@ pub fn init(memory_controller: &mut MemoryController) {
         set_cs(code_selector);
         // load TSS
         load_tss(tss_selector);
-        // Disable PIC
-        apic::init();
+        // Disable PIC and enable the local APIC
+        apic::init(memory_controller);
     }
+    init_io_apics(memory_controller);
 
     IDT.load();
+
+    unsafe {
+        interrupts::enable();
+    }
+}
+
+// Sets up the I/O APICs and ISA IRQ overrides the MADT lists, or the one I/O APIC every PC has
+// if there is no MADT
+fn init_io_apics(memory_controller: &mut MemoryController) {
+    let madt = match acpi::tables().and_then(|tables| tables.madt.as_ref()) {
+        Some(madt) if !madt.io_apics.is_empty() => madt,
+        _ => {
+            ioapic::add_io_apic(memory_controller, ioapic::DEFAULT_IO_APIC_ADDRESS, 0);
+            return;
+        },
+    };
+
+    for io_apic in &madt.io_apics {
+        ioapic::add_io_apic(memory_controller, io_apic.address as usize, io_apic.gsi_base);
+    }
+    for source_override in &madt.source_overrides {
+        // Bus 0 is ISA, the only bus overrides are defined for
+        if source_override.bus == 0 {
+            ioapic::add_source_override(source_override.irq, ioapic::SourceOverride {
+                gsi: source_override.gsi,
+                polarity: source_override.polarity,
+                trigger_mode: source_override.trigger_mode,
+            });
+        }
+    }
 }
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: &mut ExceptionStackFrame) {
-    kprintln!("Exception: BREAK_POINT {:#?}", stack_frame);
+// Enables interrupts and halts until the next one arrives. `sti` only takes effect after the
+// following instruction, so no interrupt can be handled between the two and leave the CPU
+// halted even though the caller's condition is already met.
+pub fn enable_and_halt() {
+    unsafe {
+        asm!("sti; hlt" :::: "volatile");
+    }
 }
 
-extern "x86-interrupt" fn double_fault_handler(stack_frame: &mut ExceptionStackFrame, _error_code: u64) {
-    kprintln!("Exception: DOUBLE_FAULT Code {:#x} {:#?}", _error_code, stack_frame);
-    loop {}
+// Runs `f` with interrupts disabled and restores the interrupt flag afterwards
+pub fn without_interrupts<F: FnOnce() -> R, R>(f: F) -> R {
+    use x86_64::registers::flags::{self, IF};
+    use x86_64::instructions::interrupts;
+
+    let enabled = flags::flags().contains(IF);
+    if enabled {
+        unsafe { interrupts::disable(); }
+    }
+    let result = f();
+    if enabled {
+        unsafe { interrupts::enable(); }
+    }
+    result
 }
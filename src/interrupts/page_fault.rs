@@ -0,0 +1,75 @@
+use spin::Mutex;
+
+use x86_64::structures::idt::{ExceptionStackFrame, PageFaultErrorCode};
+use x86_64::structures::idt::{PROTECTION_VIOLATION, CAUSED_BY_WRITE, USER_MODE, MALFORMED_TABLE, INSTRUCTION_FETCH};
+
+use backtrace;
+use io::term::console;
+use memory::paging;
+use power;
+use super::exceptions;
+
+// Tries to resolve a page fault at the given address. Returns whether it did, in which case the
+// faulting instruction is restarted.
+pub type PageFaultHook = fn(address: usize, error_code: PageFaultErrorCode) -> bool;
+
+const MAX_HOOKS: usize = 8;
+
+#[derive(Clone, Copy)]
+struct HookRegion {
+    start: usize,
+    end: usize,
+    hook: PageFaultHook,
+}
+
+static HOOKS: Mutex<[Option<HookRegion>; MAX_HOOKS]> = Mutex::new([None; MAX_HOOKS]);
+
+// Calls `hook` for every page fault in `[start, end)`, e.g. to back a region with frames on
+// first touch
+pub fn register_hook(start: usize, end: usize, hook: PageFaultHook) {
+    assert!(start < end, "invalid page fault region {:#x}..{:#x}", start, end);
+
+    let mut hooks = HOOKS.lock();
+    let slot = hooks.iter_mut().find(|slot| slot.is_none())
+        .expect("Too many page fault hooks!");
+    *slot = Some(HookRegion {
+        start,
+        end,
+        hook,
+    });
+}
+
+fn find_hook(address: usize) -> Option<PageFaultHook> {
+    // Don't deadlock if the fault happened while a hook was being registered
+    HOOKS.try_lock().and_then(|hooks| {
+        hooks.iter()
+            .filter_map(|region| *region)
+            .find(|region| region.start <= address && address < region.end)
+            .map(|region| region.hook)
+    })
+}
+
+pub extern "x86-interrupt" fn page_fault_handler(stack_frame: &mut ExceptionStackFrame, error_code: PageFaultErrorCode) {
+    use x86_64::registers::control_regs;
+
+    let address = control_regs::cr2().0;
+
+    if let Some(hook) = find_hook(address) {
+        if hook(address, error_code) {
+            return;
+        }
+    }
+
+    unsafe { console::take_over() };
+    exceptions::dump("PAGE_FAULT", 14, stack_frame, Some(error_code.bits()));
+    kprintln!("  {} at {:#x}, {} in {} mode{}{}",
+        if error_code.contains(PROTECTION_VIOLATION) { "protection violation" } else { "page not present" },
+        address,
+        if error_code.contains(CAUSED_BY_WRITE) { "write" } else { "read" },
+        if error_code.contains(USER_MODE) { "user" } else { "kernel" },
+        if error_code.contains(MALFORMED_TABLE) { ", reserved bit set in page table" } else { "" },
+        if error_code.contains(INSTRUCTION_FETCH) { ", instruction fetch" } else { "" });
+    kprintln!("{}", paging::walk(address));
+    backtrace::print_exception(stack_frame.instruction_pointer.0);
+    power::after_panic()
+}
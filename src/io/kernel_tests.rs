@@ -0,0 +1,21 @@
+use super::serial::{self, SerialConfig};
+use super::term::console;
+
+kernel_test! {
+    fn com1_has_the_default_configuration() {
+        // The test run is logged to COM1, see grub-test.cfg
+        let com1 = match *serial::COM1 {
+            Ok(ref com1) => com1,
+            Err(error) => panic!("COM1 is required for the kernel tests: {}", error),
+        };
+        let config = com1.lock().config();
+        assert_eq!(config, SerialConfig::default());
+    }
+}
+
+kernel_test! {
+    fn console_select_rejects_unknown_sinks() {
+        assert_eq!(console::select("serial,nonexistent"), Err("nonexistent"));
+        assert_eq!(console::is_enabled("serial"), Some(true));
+    }
+}
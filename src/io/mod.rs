@@ -1,7 +1,9 @@
 #[macro_use]
 pub mod term;
 
+mod kernel_tests;
 pub mod port;
+pub mod ring_buffer;
 pub mod serial;
 pub mod vga;
 
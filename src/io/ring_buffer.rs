@@ -0,0 +1,125 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub const RING_BUFFER_SIZE: usize = 1024;
+
+// Byte queue for one producer and one consumer. Neither side ever blocks or takes a lock, so an
+// interrupt handler can share it with the code it interrupts. Several callers may share a side
+// only if they can't run at the same time, e.g. because interrupts are disabled around them.
+pub struct RingBuffer {
+    buffer: UnsafeCell<[u8; RING_BUFFER_SIZE]>,
+    // Both indices only ever increase and wrap around at `usize::MAX`. `head` is only written by
+    // the consumer, `tail` only by the producer.
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl Sync for RingBuffer {}
+
+#[allow(dead_code)]
+impl RingBuffer {
+    pub const fn new() -> RingBuffer {
+        RingBuffer {
+            buffer: UnsafeCell::new([0; RING_BUFFER_SIZE]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.load(Ordering::Acquire).wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == RING_BUFFER_SIZE
+    }
+
+    // Must only be called by the producer. Returns `false` if the buffer is full.
+    pub fn push(&self, byte: u8) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) == RING_BUFFER_SIZE {
+            return false;
+        }
+        unsafe {
+            (*self.buffer.get())[tail % RING_BUFFER_SIZE] = byte;
+        }
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    // Must only be called by the consumer
+    pub fn pop(&self) -> Option<u8> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let byte = unsafe { (*self.buffer.get())[head % RING_BUFFER_SIZE] };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::Ordering;
+    use core::usize;
+
+    use super::{RingBuffer, RING_BUFFER_SIZE};
+
+    #[test]
+    fn new_buffer_is_empty() {
+        let buffer = RingBuffer::new();
+        assert!(buffer.is_empty() && !buffer.is_full());
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn full_buffer_rejects_bytes() {
+        let buffer = RingBuffer::new();
+        for i in 0..RING_BUFFER_SIZE {
+            assert!(buffer.push(i as u8));
+        }
+        assert!(buffer.is_full());
+        assert!(!buffer.push(0));
+        assert_eq!(buffer.len(), RING_BUFFER_SIZE);
+
+        // Popping one makes room for one
+        assert_eq!(buffer.pop(), Some(0));
+        assert!(buffer.push(0));
+        assert!(!buffer.push(0));
+    }
+
+    #[test]
+    fn bytes_keep_their_order_across_wrap_around() {
+        let buffer = RingBuffer::new();
+        for round in 0..3 {
+            for i in 0..RING_BUFFER_SIZE {
+                assert!(buffer.push((round + i) as u8));
+            }
+            for i in 0..RING_BUFFER_SIZE {
+                assert_eq!(buffer.pop(), Some((round + i) as u8));
+            }
+            assert!(buffer.is_empty());
+        }
+    }
+
+    #[test]
+    fn indices_wrap_around_at_the_integer_limit() {
+        let buffer = RingBuffer::new();
+        buffer.head.store(usize::MAX - 1, Ordering::Relaxed);
+        buffer.tail.store(usize::MAX - 1, Ordering::Relaxed);
+        for i in 0..4 {
+            assert!(buffer.push(i));
+        }
+        assert_eq!(buffer.len(), 4);
+        for i in 0..4 {
+            assert_eq!(buffer.pop(), Some(i));
+        }
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+#![allow(dead_code)]
+
+// Line settings of a serial port. The default is 115200 baud, 8N1 without flow control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    // Received bytes in the FIFO before the UART interrupts
+    pub fifo_trigger: FifoTrigger,
+    // Only send while CTS is asserted and drop RTS while the receive buffer is almost full
+    pub flow_control: bool,
+}
+
+impl Default for SerialConfig {
+    fn default() -> SerialConfig {
+        SerialConfig {
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            fifo_trigger: FifoTrigger::Fourteen,
+            flow_control: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DataBits {
+    Five = 0b00,
+    Six = 0b01,
+    Seven = 0b10,
+    Eight = 0b11,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Parity {
+    None = 0b000,
+    Odd = 0b001,
+    Even = 0b011,
+    // The parity bit is always set
+    Mark = 0b101,
+    // The parity bit is always clear
+    Space = 0b111,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StopBits {
+    One = 0,
+    // 1.5 stop bits with five data bits
+    Two = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FifoTrigger {
+    One = 0b00,
+    Four = 0b01,
+    Eight = 0b10,
+    Fourteen = 0b11,
+}
+
+impl SerialConfig {
+    // Line control register value for these settings, with the divisor latch disabled
+    pub(super) fn line_control(&self) -> u8 {
+        self.data_bits as u8 | (self.stop_bits as u8) << 2 | (self.parity as u8) << 3
+    }
+
+    // Decodes the line settings of a line control register value
+    pub(super) fn set_line_control(&mut self, lcr: u8) {
+        self.data_bits = match lcr & 0b11 {
+            0b00 => DataBits::Five,
+            0b01 => DataBits::Six,
+            0b10 => DataBits::Seven,
+            _ => DataBits::Eight,
+        };
+        self.stop_bits = if lcr & 1 << 2 != 0 { StopBits::Two } else { StopBits::One };
+        self.parity = match (lcr >> 3) & 0b111 {
+            0b001 => Parity::Odd,
+            0b011 => Parity::Even,
+            0b101 => Parity::Mark,
+            0b111 => Parity::Space,
+            _ => Parity::None,
+        };
+    }
+}
+
+bitflags! {
+    pub struct ModemControl: u8 {
+        const DTR       = 1 << 0;
+        const RTS       = 1 << 1;
+        const OUT1      = 1 << 2;
+        // Connects the interrupt line of the UART to the interrupt controller on PCs
+        const OUT2      = 1 << 3;
+        // Feeds the transmitter back into the receiver and the modem outputs into the inputs
+        const LOOPBACK  = 1 << 4;
+    }
+}
+
+bitflags! {
+    pub struct ModemStatus: u8 {
+        // Bits 0-3 tell which of the inputs changed since the last read
+        const DELTA_CTS          = 1 << 0;
+        const DELTA_DSR          = 1 << 1;
+        const TRAILING_EDGE_RI   = 1 << 2;
+        const DELTA_DCD          = 1 << 3;
+        const CTS                = 1 << 4;
+        const DSR                = 1 << 5;
+        const RI                 = 1 << 6;
+        const DCD                = 1 << 7;
+    }
+}
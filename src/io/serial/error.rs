@@ -0,0 +1,24 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    // Nothing answers at the port's I/O address
+    NotPresent,
+    // Something answers at the address, but the loopback self test failed
+    SelfTestFailed,
+    // The baud rate is zero or does not divide the UART clock evenly
+    InvalidBaudRate(u32),
+    InvalidDivisor,
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerialError::NotPresent => write!(f, "not present"),
+            SerialError::SelfTestFailed => write!(f, "loopback self test failed"),
+            SerialError::InvalidBaudRate(baud) =>
+                write!(f, "{} baud is not a divisor of {}", baud, super::SERIAL_CLOCK_BASE),
+            SerialError::InvalidDivisor => write!(f, "the baud rate divisor must not be zero"),
+        }
+    }
+}
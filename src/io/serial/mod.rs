@@ -0,0 +1,605 @@
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use interrupts::{self, irq, ioapic};
+use super::term::ansi::{self, AnsiWrite, AnsiSequence};
+use super::term::console::ConsoleSink;
+use super::ring_buffer::{RingBuffer, RING_BUFFER_SIZE};
+use super::Port;
+
+mod config;
+mod error;
+mod probe;
+
+#[allow(unused_imports)]
+pub use self::config::{SerialConfig, DataBits, Parity, StopBits, FifoTrigger, ModemControl, ModemStatus};
+pub use self::error::SerialError;
+#[allow(unused_imports)]
+pub use self::probe::{UartType, ports, report};
+
+#[allow(dead_code)]
+#[repr(u16)]
+enum SerialIoPort {
+    COM1 = 0x3F8,
+    COM2 = 0x2F8,
+    COM3 = 0x3E8,
+    COM4 = 0x2E8,
+}
+
+#[allow(dead_code)]
+#[repr(u16)]
+// Offsets for the base io port
+enum Register {
+    Data = 0,
+    // The 1 << 7 is just to differentiate between data and divisor latch
+    DivisorLatchHigh = 1 | 1 << 7,
+    DivisorLatchLow = 0 | 1 << 7,
+    InterruptEnable = 1,
+    // Read only, it shares its offset with the write only FIFO control
+    InterruptIdentification = 2 | 1 << 8,
+    LineControl = 3,
+    LineStatus = 5,
+    FIFOControl = 2,
+    ModemControl = 4,
+    ModemStatus = 6,
+    Scratch = 7,
+}
+
+const SERIAL_CLOCK_BASE: u32 = 115200;
+
+const INTERRUPT_RX_AVAILABLE: u8 = 1 << 0;
+const INTERRUPT_TX_EMPTY: u8 = 1 << 1;
+const INTERRUPT_MODEM_STATUS: u8 = 1 << 3;
+
+const FIFO_ENABLE: u8 = 1 << 0;
+const FIFO_CLEAR_RX: u8 = 1 << 1;
+const FIFO_CLEAR_TX: u8 = 1 << 2;
+
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+const LINE_STATUS_TX_EMPTY: u8 = 1 << 5;
+
+// With flow control, RTS is dropped when the receive buffer is this full and raised again once
+// it drained to the low mark
+const RX_HIGH_WATER: usize = RING_BUFFER_SIZE * 3 / 4;
+const RX_LOW_WATER: usize = RING_BUFFER_SIZE / 4;
+
+// How often the line status is polled for the loopback byte before the port is considered absent
+const SELF_TEST_POLLS: usize = 10_000;
+
+const IIR_NO_INTERRUPT: u8 = 1 << 0;
+const IIR_MODEM_STATUS: u8 = 0b000;
+const IIR_TX_EMPTY: u8 = 0b001;
+const IIR_RX_AVAILABLE: u8 = 0b010;
+const IIR_LINE_STATUS: u8 = 0b011;
+const IIR_RX_TIMEOUT: u8 = 0b110;
+
+// COM1 and COM3 share IRQ 4, COM2 and COM4 IRQ 3
+const COM1_COM3_VECTOR: u8 = irq::FIRST_VECTOR + ioapic::IRQ_COM1;
+const COM2_COM4_VECTOR: u8 = irq::FIRST_VECTOR + ioapic::IRQ_COM2;
+
+// State a port shares with its interrupt handler in interrupt-driven mode. The handler is the
+// producer of `rx` and the consumer of `tx`. The port's owner is the other side of both, and
+// also produces `rx` and consumes `tx` itself with interrupts disabled, see `drain_tx`.
+struct SerialBuffers {
+    rx: RingBuffer,
+    tx: RingBuffer,
+    interrupt_driven: AtomicBool,
+    flow_control: AtomicBool,
+    // Whether RTS was dropped because `rx` is almost full
+    rx_throttled: AtomicBool,
+    // Bytes received while `rx` was full
+    rx_dropped: AtomicUsize,
+}
+
+impl SerialBuffers {
+    const fn new() -> SerialBuffers {
+        SerialBuffers {
+            rx: RingBuffer::new(),
+            tx: RingBuffer::new(),
+            interrupt_driven: AtomicBool::new(false),
+            flow_control: AtomicBool::new(false),
+            rx_throttled: AtomicBool::new(false),
+            rx_dropped: AtomicUsize::new(0),
+        }
+    }
+}
+
+static BUFFERS: [SerialBuffers; 4] = [
+    SerialBuffers::new(),
+    SerialBuffers::new(),
+    SerialBuffers::new(),
+    SerialBuffers::new(),
+];
+
+// Holds why a port is unusable if probing it failed, usually because it doesn't exist
+#[allow(dead_code)]
+lazy_static! {
+    #[allow(dead_code)]
+    pub static ref COM1: Result<Mutex<SerialPort>, SerialError> = SerialPort::init(SerialIoPort::COM1).map(Mutex::new);
+    #[allow(dead_code)]
+    pub static ref COM2: Result<Mutex<SerialPort>, SerialError> = SerialPort::init(SerialIoPort::COM2).map(Mutex::new);
+    #[allow(dead_code)]
+    pub static ref COM3: Result<Mutex<SerialPort>, SerialError> = SerialPort::init(SerialIoPort::COM3).map(Mutex::new);
+    #[allow(dead_code)]
+    pub static ref COM4: Result<Mutex<SerialPort>, SerialError> = SerialPort::init(SerialIoPort::COM4).map(Mutex::new);
+}
+
+// Switches all COM ports to interrupt-driven mode. The I/O APIC must be set up already.
+pub fn enable_interrupts() {
+    ioapic::route_irq(ioapic::IRQ_COM1, COM1_COM3_VECTOR, com1_com3_interrupt);
+    ioapic::route_irq(ioapic::IRQ_COM2, COM2_COM4_VECTOR, com2_com4_interrupt);
+
+    for port in [&*COM1, &*COM2, &*COM3, &*COM4].iter() {
+        if let Ok(ref port) = **port {
+            port.lock().enable_interrupts();
+        }
+    }
+}
+
+// The ring buffers have one producer and one consumer. Code that takes the interrupt handler's
+// side of one must keep it from running in between.
+fn assert_interrupts_disabled() {
+    use x86_64::registers::flags::{self, IF};
+
+    assert!(!flags::flags().contains(IF), "serial buffers accessed with interrupts enabled");
+}
+
+fn com1_com3_interrupt() {
+    SerialPort::new(SerialIoPort::COM1).service();
+    SerialPort::new(SerialIoPort::COM3).service();
+}
+
+fn com2_com4_interrupt() {
+    SerialPort::new(SerialIoPort::COM2).service();
+    SerialPort::new(SerialIoPort::COM4).service();
+}
+
+pub struct SerialPort {
+    port: Port<u8>,
+    buffers: &'static SerialBuffers,
+    // The FIFO control register is write only, so remember what was programmed
+    fifo_trigger: FifoTrigger,
+    uart_type: UartType,
+}
+
+#[allow(dead_code)]
+impl SerialPort {
+    fn new(io_base: SerialIoPort) -> SerialPort {
+        let buffers = match io_base {
+            SerialIoPort::COM1 => &BUFFERS[0],
+            SerialIoPort::COM2 => &BUFFERS[1],
+            SerialIoPort::COM3 => &BUFFERS[2],
+            SerialIoPort::COM4 => &BUFFERS[3],
+        };
+        SerialPort {
+            port: unsafe { Port::new(io_base as u16) },
+            buffers,
+            fifo_trigger: FifoTrigger::Fourteen,
+            uart_type: UartType::Uart8250,
+        }
+    }
+
+    fn init(io_base: SerialIoPort) -> Result<SerialPort, SerialError> {
+        let mut port = SerialPort::new(io_base);
+        let has_scratch_register = port.has_scratch_register();
+        port.uart_type = port.detect_uart_type(has_scratch_register);
+
+        port.write_register(Register::InterruptEnable, 0);
+        port.configure(&SerialConfig::default())?;
+        if port.self_test() {
+            Ok(port)
+        }
+        else if has_scratch_register {
+            Err(SerialError::SelfTestFailed)
+        }
+        else {
+            Err(SerialError::NotPresent)
+        }
+    }
+
+    pub fn uart_type(&self) -> UartType {
+        self.uart_type
+    }
+
+    // Echoes a byte through the loopback mode of the UART. This fails if there is no UART.
+    fn self_test(&mut self) -> bool {
+        const TEST_BYTE: u8 = 0xae;
+
+        let modem_control = self.read_register(Register::ModemControl);
+        self.write_register(Register::ModemControl,
+            (ModemControl::LOOPBACK | ModemControl::RTS | ModemControl::OUT1).bits());
+        // Discard a stale byte in case there is no FIFO that was cleared
+        self.read_register(Register::Data);
+
+        self.write_register(Register::Data, TEST_BYTE);
+        let received = (0..SELF_TEST_POLLS)
+            .filter_map(|_| self.poll_byte())
+            .next();
+
+        self.write_register(Register::ModemControl, modem_control);
+        received == Some(TEST_BYTE)
+    }
+
+    // Applies the line settings. Bytes still in the UART's FIFOs are discarded. Nothing is
+    // changed if the settings are invalid.
+    pub fn configure(&mut self, config: &SerialConfig) -> Result<(), SerialError> {
+        self.set_baud_rate(config.baud_rate)?;
+        self.write_register(Register::LineControl, config.line_control());
+        self.write_register(Register::FIFOControl,
+            FIFO_ENABLE | FIFO_CLEAR_RX | FIFO_CLEAR_TX | (config.fifo_trigger as u8) << 6);
+        self.fifo_trigger = config.fifo_trigger;
+
+        self.buffers.flow_control.store(config.flow_control, Ordering::Release);
+        self.buffers.rx_throttled.store(false, Ordering::Release);
+        let mut modem_control = ModemControl::DTR | ModemControl::RTS;
+        if self.is_interrupt_driven() {
+            modem_control |= ModemControl::OUT2;
+        }
+        self.set_modem_control(modem_control);
+        Ok(())
+    }
+
+    // Reads the current line settings back from the UART
+    pub fn config(&mut self) -> SerialConfig {
+        let mut config = SerialConfig {
+            baud_rate: self.get_baud_rate(),
+            fifo_trigger: self.fifo_trigger,
+            flow_control: self.buffers.flow_control.load(Ordering::Acquire),
+            ..SerialConfig::default()
+        };
+        config.set_line_control(self.read_register(Register::LineControl));
+        config
+    }
+
+    pub fn modem_control(&mut self) -> ModemControl {
+        ModemControl::from_bits_truncate(self.read_register(Register::ModemControl))
+    }
+
+    pub fn set_modem_control(&mut self, modem_control: ModemControl) {
+        self.write_register(Register::ModemControl, modem_control.bits());
+    }
+
+    pub fn modem_status(&mut self) -> ModemStatus {
+        ModemStatus::from_bits_truncate(self.read_register(Register::ModemStatus))
+    }
+
+    // Whether the other side accepts data. Always true without flow control.
+    fn clear_to_send(&mut self) -> bool {
+        !self.buffers.flow_control.load(Ordering::Acquire)
+            || self.modem_status().contains(ModemStatus::CTS)
+    }
+
+    // Drops or raises RTS depending on how full the receive buffer is. Runs with interrupts
+    // disabled since both the interrupt handler and readers change the modem control register.
+    fn update_rts(&mut self) {
+        if !self.buffers.flow_control.load(Ordering::Acquire) {
+            return;
+        }
+        let throttled = self.buffers.rx_throttled.load(Ordering::Acquire);
+        let len = self.buffers.rx.len();
+        if !throttled && len >= RX_HIGH_WATER {
+            let modem_control = self.modem_control() - ModemControl::RTS;
+            self.set_modem_control(modem_control);
+            self.buffers.rx_throttled.store(true, Ordering::Release);
+        }
+        else if throttled && len <= RX_LOW_WATER {
+            let modem_control = self.modem_control() | ModemControl::RTS;
+            self.set_modem_control(modem_control);
+            self.buffers.rx_throttled.store(false, Ordering::Release);
+        }
+    }
+
+    fn register_port(&mut self, register: Register) -> Port<u8> {
+        unsafe { self.port.offset(register as u16 & 0x7F) }
+    }
+
+    pub fn is_interrupt_driven(&self) -> bool {
+        self.buffers.interrupt_driven.load(Ordering::Acquire)
+    }
+
+    // Receives and sends through ring buffers filled and drained by the UART's interrupts from
+    // now on
+    pub fn enable_interrupts(&mut self) {
+        // Pick up what was received so far before the interrupt handler takes over
+        while let Some(byte) = self.poll_byte() {
+            self.buffers.rx.push(byte);
+        }
+        self.buffers.interrupt_driven.store(true, Ordering::Release);
+        let modem_control = self.modem_control() | ModemControl::OUT2;
+        self.set_modem_control(modem_control);
+        self.write_register(Register::InterruptEnable,
+            INTERRUPT_RX_AVAILABLE | INTERRUPT_TX_EMPTY | INTERRUPT_MODEM_STATUS);
+    }
+
+    // Bytes that were lost because the receive buffer was full
+    pub fn dropped_bytes(&self) -> usize {
+        self.buffers.rx_dropped.load(Ordering::Relaxed)
+    }
+
+    // Handles all pending interrupt conditions of the UART. Must run with interrupts disabled so
+    // it never races with the interrupt handler.
+    fn service(&mut self) {
+        assert_interrupts_disabled();
+        loop {
+            let iir = self.read_register(Register::InterruptIdentification);
+            if iir & IIR_NO_INTERRUPT != 0 {
+                break;
+            }
+            match (iir >> 1) & 0b111 {
+                IIR_RX_AVAILABLE | IIR_RX_TIMEOUT => {
+                    while let Some(byte) = self.poll_byte() {
+                        if !self.buffers.rx.push(byte) {
+                            self.buffers.rx_dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    self.update_rts();
+                },
+                IIR_TX_EMPTY => self.drain_tx(),
+                // Reading the status registers acknowledges these
+                IIR_LINE_STATUS => { self.read_register(Register::LineStatus); },
+                IIR_MODEM_STATUS => {
+                    // CTS might have been asserted
+                    self.modem_status();
+                    self.drain_tx();
+                },
+                _ => break,
+            }
+        }
+    }
+
+    // Moves bytes from the transmit buffer to the UART while it has room. Both the interrupt
+    // handler and the writers call it, which makes them all consumers of `tx`. That's only safe
+    // because interrupts are disabled while it runs, so none of them can interrupt another.
+    fn drain_tx(&mut self) {
+        assert_interrupts_disabled();
+        while self.has_write_space() && self.clear_to_send() {
+            match self.buffers.tx.pop() {
+                Some(byte) => self.write_register(Register::Data, byte),
+                None => break,
+            }
+        }
+    }
+
+    // Halts until `done` holds. The check and the halt happen with interrupts disabled, so the
+    // interrupt that makes it true can't slip in between and leave the CPU halted.
+    fn wait_until<F: Fn(&SerialPort) -> bool>(&mut self, done: F) {
+        use x86_64::registers::flags::{self, IF};
+        use x86_64::instructions::interrupts as cpu;
+
+        if !flags::flags().contains(IF) {
+            // The interrupt handler can't run, so do its work here
+            while !done(self) {
+                self.service();
+                self.drain_tx();
+            }
+            return;
+        }
+
+        loop {
+            unsafe { cpu::disable(); }
+            if done(self) {
+                unsafe { cpu::enable(); }
+                return;
+            }
+            interrupts::enable_and_halt();
+        }
+    }
+
+    fn read_register(&mut self, register: Register) -> u8 {
+        self.register_port(register).read()
+    }
+
+    fn write_register(&mut self, register: Register, val: u8) {
+        self.register_port(register).write(val)
+    }
+
+    fn set_dlab(&mut self, enabled: bool) {
+        let mut lcr = self.read_register(Register::LineControl);
+        if enabled {
+            lcr |= 0x80;
+        }
+        else {
+            lcr &= 0x7F;
+        }
+        self.write_register(Register::LineControl, lcr);
+    }
+
+    pub fn get_baud_rate_divisor(&mut self) -> u16 {
+        interrupts::without_interrupts(|| {
+            self.set_dlab(true);
+            let high = self.read_register(Register::DivisorLatchHigh) as u16;
+            let low = self.read_register(Register::DivisorLatchLow) as u16;
+            self.set_dlab(false);
+            (high << 8) + low
+        })
+    }
+
+    pub fn set_baud_rate_divisor(&mut self, divisor: u16) -> Result<(), SerialError> {
+        if divisor == 0 {
+            return Err(SerialError::InvalidDivisor);
+        }
+        let high = (divisor >> 8) as u8;
+        let low = divisor as u8;
+        // The interrupt handler would read the divisor latch instead of the data register
+        interrupts::without_interrupts(|| {
+            self.set_dlab(true);
+            self.write_register(Register::DivisorLatchHigh, high);
+            self.write_register(Register::DivisorLatchLow, low);
+            self.set_dlab(false);
+        });
+        Ok(())
+    }
+
+    // Zero if the divisor was never programmed
+    pub fn get_baud_rate(&mut self) -> u32 {
+        match self.get_baud_rate_divisor() {
+            0 => 0,
+            divisor => SERIAL_CLOCK_BASE / divisor as u32,
+        }
+    }
+
+    pub fn set_baud_rate(&mut self, baud: u32) -> Result<(), SerialError> {
+        if baud == 0 || SERIAL_CLOCK_BASE % baud != 0 {
+            return Err(SerialError::InvalidBaudRate(baud));
+        }
+        self.set_baud_rate_divisor((SERIAL_CLOCK_BASE / baud) as u16)
+    }
+
+    pub fn has_available_byte(&mut self) -> bool {
+        if self.is_interrupt_driven() {
+            !self.buffers.rx.is_empty()
+        }
+        else {
+            self.read_register(Register::LineStatus) & LINE_STATUS_DATA_READY != 0
+        }
+    }
+
+    fn poll_byte(&mut self) -> Option<u8> {
+        if self.read_register(Register::LineStatus) & LINE_STATUS_DATA_READY != 0 {
+            Some(self.read_register(Register::Data))
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn read_byte(&mut self) -> Option<u8> {
+        if self.is_interrupt_driven() {
+            let byte = self.buffers.rx.pop();
+            if self.buffers.rx_throttled.load(Ordering::Acquire) {
+                interrupts::without_interrupts(|| self.update_rts());
+            }
+            byte
+        }
+        else {
+            self.poll_byte()
+        }
+    }
+
+    // Waits for the next byte. In interrupt-driven mode the CPU is halted in between.
+    pub fn read_byte_blocking(&mut self) -> u8 {
+        loop {
+            if let Some(byte) = self.read_byte() {
+                return byte;
+            }
+            if self.is_interrupt_driven() {
+                self.wait_until(|port| !port.buffers.rx.is_empty());
+            }
+        }
+    }
+
+    pub fn has_write_space(&mut self) -> bool {
+        self.read_register(Register::LineStatus) & LINE_STATUS_TX_EMPTY != 0
+    }
+
+    pub fn write_byte_sync(&mut self, val: u8) {
+        if self.is_interrupt_driven() {
+            // Never drop a byte, wait for the interrupt handler to make room instead
+            while !self.buffers.tx.push(val) {
+                self.wait_until(|port| !port.buffers.tx.is_full());
+            }
+            // The transmitter only interrupts when it runs empty, so start it if it is idle
+            interrupts::without_interrupts(|| self.drain_tx());
+            return;
+        }
+        loop {
+            if self.write_byte(val) {
+                break;
+            }
+        }
+    }
+
+    // Writes the byte if the UART has room right now. In interrupt-driven mode it is queued
+    // behind the buffered bytes instead.
+    pub fn write_byte(&mut self, val: u8) -> bool {
+        if self.is_interrupt_driven() {
+            let queued = self.buffers.tx.push(val);
+            interrupts::without_interrupts(|| self.drain_tx());
+            queued
+        }
+        else if !self.has_write_space() || !self.clear_to_send() {
+            false
+        }
+        else {
+            self.write_register(Register::Data, val);
+            true
+        }
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        for b in s.bytes() {
+            self.write_byte_sync(b);
+        }
+    }
+}
+
+struct SerialWriter<'a>(&'a mut SerialPort);
+
+#[allow(dead_code)]
+impl<'a> SerialWriter<'a> {
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            byte => {
+                self.0.write_byte_sync(byte);
+            }
+        }
+    }
+
+    pub fn write_ansi(&mut self, s: &str) {
+        self.write_ansi_str(s).unwrap();
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        for byte in s.bytes() {
+            self.0.write_byte_sync(byte);
+        }
+    }
+
+    pub fn new_line(&mut self) {
+        self.0.write_str("\r\n");
+    }
+}
+
+impl<'a> fmt::Write for SerialWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_ansi(s);
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.write_byte(c as u8);
+        Ok(())
+    }
+}
+
+impl<'a> AnsiWrite for SerialWriter<'a> {
+    // We can pass the sequence right through since we're not the ones displaying it
+    fn write_ansi_sequence(&mut self, seq: AnsiSequence) -> fmt::Result {
+        use core::fmt::Write;
+        self.write_char(ansi::ESCAPE).unwrap();
+        self.write_str(seq.to_string().as_str());
+        Ok(())
+    }
+}
+
+// Console sink that writes to COM1 if it exists
+pub struct SerialConsole;
+
+pub static CONSOLE: SerialConsole = SerialConsole;
+
+impl ConsoleSink for SerialConsole {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        match *COM1 {
+            Ok(ref com1) => SerialWriter(&mut com1.lock()).write_ansi_str(s),
+            Err(_) => Ok(()),
+        }
+    }
+
+    unsafe fn force_unlock(&self) {
+        if let Ok(ref com1) = *COM1 {
+            com1.force_unlock();
+        }
+    }
+}
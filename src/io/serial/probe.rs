@@ -0,0 +1,99 @@
+use core::fmt;
+
+use super::{SerialPort, SerialError, Register, COM1, COM2, COM3, COM4};
+
+const FIFO_ENABLE_64_BYTE: u8 = 1 << 5;
+
+// UART generations, told apart by their FIFOs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartType {
+    // No FIFO and no scratch register
+    Uart8250,
+    // No FIFO
+    Uart16450,
+    // FIFO that is too buggy to be used
+    Uart16550,
+    // 16 byte FIFO
+    Uart16550A,
+    // 64 byte FIFO
+    Uart16750,
+}
+
+impl fmt::Display for UartType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            UartType::Uart8250 => "8250",
+            UartType::Uart16450 => "16450",
+            UartType::Uart16550 => "16550",
+            UartType::Uart16550A => "16550A",
+            UartType::Uart16750 => "16750",
+        })
+    }
+}
+
+impl SerialPort {
+    // Anything but the original 8250 keeps what is written to the scratch register. Reads from
+    // an address without a device return all ones, which fails as well.
+    pub(super) fn has_scratch_register(&mut self) -> bool {
+        [0x55, 0xaa].iter().all(|&pattern| {
+            self.write_register(Register::Scratch, pattern);
+            self.read_register(Register::Scratch) == pattern
+        })
+    }
+
+    // Enables the FIFOs and checks which bits of the interrupt identification say they are on.
+    // A 16750 only takes the 64 byte FIFO bit while the divisor latch is selected. The FIFO
+    // control register must be programmed again afterwards.
+    pub(super) fn detect_uart_type(&mut self, has_scratch_register: bool) -> UartType {
+        self.set_dlab(true);
+        self.write_register(Register::FIFOControl, 0xc7 | FIFO_ENABLE_64_BYTE);
+        self.set_dlab(false);
+        let iir = self.read_register(Register::InterruptIdentification);
+
+        if iir & 1 << 6 != 0 {
+            if iir & 1 << 7 == 0 {
+                UartType::Uart16550
+            }
+            else if iir & 1 << 5 != 0 {
+                UartType::Uart16750
+            }
+            else {
+                UartType::Uart16550A
+            }
+        }
+        else if has_scratch_register {
+            UartType::Uart16450
+        }
+        else {
+            UartType::Uart8250
+        }
+    }
+}
+
+// What was found at COM1 to COM4. This locks every port that exists.
+pub fn ports() -> [(&'static str, Result<UartType, SerialError>); 4] {
+    fn probe(port: &Result<::spin::Mutex<SerialPort>, SerialError>) -> Result<UartType, SerialError> {
+        match *port {
+            Ok(ref port) => Ok(port.lock().uart_type()),
+            Err(error) => Err(error),
+        }
+    }
+
+    [
+        ("COM1", probe(&COM1)),
+        ("COM2", probe(&COM2)),
+        ("COM3", probe(&COM3)),
+        ("COM4", probe(&COM4)),
+    ]
+}
+
+// Logs which serial ports exist
+pub fn report() {
+    for &(name, ref result) in ports().iter() {
+        match *result {
+            Ok(uart_type) => info!("{}: {} UART", name, uart_type),
+            Err(SerialError::NotPresent) => debug!("{}: not present", name),
+            Err(error) => warn!("{}: {}", name, error),
+        }
+    }
+}
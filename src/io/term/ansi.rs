@@ -39,7 +39,7 @@ pub trait AnsiWrite: fmt::Write {
 }
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum TextAttribute {
     Off        = 0,
@@ -93,7 +93,7 @@ pub enum ScreenMode {
     // Graphics
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[allow(dead_code)]
 pub enum AnsiSequence {
     CursorPosition { row: u8, col: u8 },
@@ -247,3 +247,111 @@ impl AnsiSequence {
         None
     }
 }
+
+// Writes the escaped sequence without allocating, unlike `to_escaped_string`
+impl fmt::Display for AnsiSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[", ESCAPE)?;
+        match *self {
+            CursorPosition { row, col } => write!(f, "{};{}H", row, col),
+            CursorUp(amount) => write!(f, "{}A", amount),
+            CursorDown(amount) => write!(f, "{}B", amount),
+            CursorForward(amount) => write!(f, "{}C", amount),
+            CursorBackward(amount) => write!(f, "{}D", amount),
+            SaveCursorPosition => write!(f, "s"),
+            RestoreCursorPosition => write!(f, "u"),
+            EraseDisplay => write!(f, "2J"),
+            EraseLine => write!(f, "K"),
+            SetGraphicsMode(ref modes) => {
+                let mut first = true;
+                for mode in modes.iter().filter_map(|mode| *mode) {
+                    if !first {
+                        write!(f, ";")?;
+                    }
+                    write!(f, "{}", mode as u8)?;
+                    first = false;
+                }
+                write!(f, "m")
+            },
+            UnknownSequence => Err(fmt::Error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::XorShift;
+
+    // Every value `TextAttribute` has a variant for
+    const ATTRIBUTES: [u8; 22] = [
+        0, 1, 2, 5, 7, 8,
+        30, 31, 32, 33, 34, 35, 36, 37,
+        40, 41, 42, 43, 44, 45, 46, 47,
+    ];
+
+    #[test]
+    fn parses_known_sequences() {
+        assert_eq!(AnsiSequence::parse("\x1b[2J"), Some(EraseDisplay));
+        assert_eq!(AnsiSequence::parse("[10;20H"), Some(CursorPosition { row: 10, col: 20 }));
+        assert_eq!(AnsiSequence::parse("[A"), Some(CursorUp(1)));
+        assert_eq!(AnsiSequence::parse("[5D"), Some(CursorBackward(5)));
+        assert_eq!(AnsiSequence::parse("[1;31m"), Some(SetGraphicsMode([Some(Bold), Some(Red), None])));
+    }
+
+    #[test]
+    fn rejects_malformed_sequences() {
+        assert_eq!(AnsiSequence::parse("x[2J"), None);
+        assert_eq!(AnsiSequence::parse("\x1bx"), None);
+        assert_eq!(AnsiSequence::parse("[12"), None);
+        assert_eq!(AnsiSequence::parse("[1:2H"), None);
+        assert_eq!(AnsiSequence::parse("[1;2;3;4m"), Some(UnknownSequence));
+        assert_eq!(AnsiSequence::parse("[3J"), Some(UnknownSequence));
+    }
+
+    fn random_argument(rng: &mut XorShift) -> u8 {
+        // Arguments with more than two digits are not supported
+        rng.below(100) as u8
+    }
+
+    fn random_sequence(rng: &mut XorShift) -> AnsiSequence {
+        match rng.below(10) {
+            0 => CursorPosition { row: random_argument(rng), col: random_argument(rng) },
+            1 => CursorUp(random_argument(rng)),
+            2 => CursorDown(random_argument(rng)),
+            3 => CursorForward(random_argument(rng)),
+            4 => CursorBackward(random_argument(rng)),
+            5 => SaveCursorPosition,
+            6 => RestoreCursorPosition,
+            7 => EraseDisplay,
+            8 => EraseLine,
+            _ => {
+                // Only leading attributes survive a round trip, gaps are closed up
+                let mut modes = [None; 3];
+                for mode in modes.iter_mut().take(rng.below(4)) {
+                    *mode = TextAttribute::from_u8(ATTRIBUTES[rng.below(ATTRIBUTES.len())]);
+                }
+                SetGraphicsMode(modes)
+            },
+        }
+    }
+
+    #[test]
+    fn sequences_survive_a_round_trip() {
+        let mut rng = XorShift::new(0xa115_1000);
+        for _ in 0..10_000 {
+            let sequence = random_sequence(&mut rng);
+            assert_eq!(AnsiSequence::parse(&sequence.to_string()), Some(sequence));
+            assert_eq!(AnsiSequence::parse(&sequence.to_escaped_string()), Some(sequence));
+        }
+    }
+
+    #[test]
+    fn display_matches_to_escaped_string() {
+        let mut rng = XorShift::new(0xd15_914);
+        for _ in 0..10_000 {
+            let sequence = random_sequence(&mut rng);
+            assert_eq!(format!("{}", sequence), sequence.to_escaped_string());
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use core::fmt;
+use spin::Mutex;
+
+// Somewhere `kprint!` output goes. Every sink does its own locking.
+pub trait ConsoleSink: Sync {
+    fn write_str(&self, s: &str) -> fmt::Result;
+
+    // Releases the sink's lock no matter who holds it. Only for the panic handler.
+    unsafe fn force_unlock(&self) {}
+}
+
+const MAX_SINKS: usize = 8;
+
+#[derive(Clone, Copy)]
+struct SinkEntry {
+    name: &'static str,
+    sink: &'static ConsoleSink,
+    enabled: bool,
+}
+
+static SINKS: Mutex<[Option<SinkEntry>; MAX_SINKS]> = Mutex::new([
+    Some(SinkEntry {
+        name: "vga",
+        sink: &super::VGA_TEXT_BUFFER,
+        enabled: true,
+    }),
+    Some(SinkEntry {
+        name: "serial",
+        sink: &::io::serial::CONSOLE,
+        enabled: true,
+    }),
+    Some(SinkEntry {
+        name: "log",
+        sink: &super::ring_log::LOG_SINK,
+        enabled: true,
+    }),
+    None, None, None, None, None,
+]);
+
+// Adds an enabled sink. Panics if there is no room or the name is taken.
+#[allow(dead_code)]
+pub fn register(name: &'static str, sink: &'static ConsoleSink) {
+    let mut sinks = SINKS.lock();
+    assert!(sinks.iter().filter_map(|entry| *entry).all(|entry| entry.name != name),
+        "console sink {} is already registered", name);
+    let slot = sinks.iter_mut().find(|slot| slot.is_none())
+        .expect("Too many console sinks!");
+    *slot = Some(SinkEntry {
+        name,
+        sink,
+        enabled: true,
+    });
+}
+
+// Turns output to the sink called `name` on or off. Returns `false` if there is no such sink.
+pub fn set_enabled(name: &str, enabled: bool) -> bool {
+    let mut sinks = SINKS.lock();
+    match sinks.iter_mut().filter_map(|entry| entry.as_mut()).find(|entry| entry.name == name) {
+        Some(entry) => {
+            entry.enabled = enabled;
+            true
+        },
+        None => false,
+    }
+}
+
+// Enables exactly the sinks in the comma separated list `names`. Nothing changes if a name is
+// unknown, which is returned instead, so a typo can't silence the console.
+pub fn select(names: &str) -> Result<(), &str> {
+    let mut sinks = SINKS.lock();
+    for name in names.split(',') {
+        if !sinks.iter().filter_map(|entry| *entry).any(|entry| entry.name == name) {
+            return Err(name);
+        }
+    }
+    for entry in sinks.iter_mut().filter_map(|entry| entry.as_mut()) {
+        entry.enabled = names.split(',').any(|name| name == entry.name);
+    }
+    Ok(())
+}
+
+pub fn is_enabled(name: &str) -> Option<bool> {
+    SINKS.lock().iter()
+        .filter_map(|entry| *entry)
+        .find(|entry| entry.name == name)
+        .map(|entry| entry.enabled)
+}
+
+// Makes sure the panic or exception message gets out: every sink is enabled and unlocked.
+// Whoever held a lock was interrupted by the panic and won't run again.
+pub unsafe fn take_over() {
+    SINKS.force_unlock();
+    for entry in SINKS.lock().iter_mut().filter_map(|entry| entry.as_mut()) {
+        entry.enabled = true;
+        entry.sink.force_unlock();
+    }
+}
+
+struct SinkWriter(&'static ConsoleSink);
+
+impl fmt::Write for SinkWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.write_str(s)
+    }
+}
+
+// Formats `args` into every enabled sink. The sink list is copied first so a sink that prints
+// itself, e.g. by panicking, doesn't deadlock on it.
+pub fn write_fmt(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    let sinks = *SINKS.lock();
+    for entry in sinks.iter().filter_map(|entry| *entry).filter(|entry| entry.enabled) {
+        // A failing sink must not keep the message from the others
+        let _ = SinkWriter(entry.sink).write_fmt(args);
+    }
+}
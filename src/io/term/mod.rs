@@ -1,21 +1,15 @@
 use core::fmt;
-use spin::{Mutex, MutexGuard};
+use spin::Mutex;
 
 pub mod ansi;
+pub mod console;
+pub mod ring_log;
 
-static PRINTER: &Mutex<PrinterDriver<::io::vga::text_buffer::Writer>> =
-    &VGA_TEXT_BUFFER;
-
-static VGA_TEXT_BUFFER: Mutex<PrinterDriver<::io::vga::text_buffer::Writer>> =
-    Mutex::new(PrinterDriver(&::io::vga::text_buffer::WRITER));
+static VGA_TEXT_BUFFER: PrinterDriver<::io::vga::text_buffer::Writer> =
+    PrinterDriver(&::io::vga::text_buffer::WRITER);
 
 pub struct PrinterDriver<'a, T: ansi::AnsiWrite + 'a>(&'a Mutex<T>);
 
-#[allow(dead_code)]
-impl<'a, T> PrinterDriver<'a, T> where T: ansi::AnsiWrite {
-    // const SERIAL_COM1: Self = PrinterDriver(::io::serial::kprint);
-}
-
 impl<'a, T> fmt::Write for PrinterDriver<'a, T> where T: ansi::AnsiWrite {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         // Use AnsiWriter here
@@ -23,6 +17,16 @@ impl<'a, T> fmt::Write for PrinterDriver<'a, T> where T: ansi::AnsiWrite {
     }
 }
 
+impl<'a, T> console::ConsoleSink for PrinterDriver<'a, T> where T: ansi::AnsiWrite + Send {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        self.0.lock().write_ansi_str(s)
+    }
+
+    unsafe fn force_unlock(&self) {
+        self.0.force_unlock();
+    }
+}
+
 macro_rules! kprint {
     ($($arg:tt)*) => ({
         $crate::io::term::kprint(format_args!($($arg)*))
@@ -34,7 +38,7 @@ macro_rules! kprintln {
     ($fmt:expr, $($arg:tt)*) => (kprint!(concat!($fmt, "\n"), $($arg)*));
 }
 
+// Writes to every enabled console sink
 pub fn kprint(args: fmt::Arguments) {
-    use core::fmt::Write;
-    PRINTER.lock().write_fmt(args).unwrap();
+    console::write_fmt(args);
 }
@@ -0,0 +1,85 @@
+use core::fmt;
+use spin::Mutex;
+
+use interrupts::without_interrupts;
+use super::console::ConsoleSink;
+
+pub const LOG_SIZE: usize = 16 * 1024;
+
+// Keeps the last `LOG_SIZE` bytes of console output, e.g. to look at after the screen scrolled
+pub struct RingLog {
+    buffer: [u8; LOG_SIZE],
+    // Index of the oldest byte
+    start: usize,
+    len: usize,
+}
+
+#[allow(dead_code)]
+impl RingLog {
+    pub const fn new() -> RingLog {
+        RingLog {
+            buffer: [0; LOG_SIZE],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    // Appends `bytes`, overwriting the oldest bytes once the log is full
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            let end = (self.start + self.len) % LOG_SIZE;
+            self.buffer[end] = byte;
+            if self.len == LOG_SIZE {
+                self.start = (self.start + 1) % LOG_SIZE;
+            }
+            else {
+                self.len += 1;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    // The logged bytes from oldest to newest, split where the buffer wraps around
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        if self.start + self.len <= LOG_SIZE {
+            (&self.buffer[self.start..self.start + self.len], &[])
+        }
+        else {
+            let wrapped = self.start + self.len - LOG_SIZE;
+            (&self.buffer[self.start..], &self.buffer[..wrapped])
+        }
+    }
+}
+
+impl fmt::Write for RingLog {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+pub static LOG: Mutex<RingLog> = Mutex::new(RingLog::new());
+
+pub struct LogSink;
+
+pub static LOG_SINK: LogSink = LogSink;
+
+impl ConsoleSink for LogSink {
+    fn write_str(&self, s: &str) -> fmt::Result {
+        // Interrupt handlers log too, so they must not find the log locked
+        without_interrupts(|| LOG.lock().write(s.as_bytes()));
+        Ok(())
+    }
+
+    unsafe fn force_unlock(&self) {
+        LOG.force_unlock();
+    }
+}
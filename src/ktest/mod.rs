@@ -0,0 +1,108 @@
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use power;
+
+// isa-debug-exit codes, QEMU exits with `(code << 1) | 1`, i.e. 33 and 35
+pub const EXIT_SUCCESS: u32 = 0x10;
+pub const EXIT_FAILURE: u32 = 0x11;
+
+// A test registered with `kernel_test!`. The linker collects them in `.kernel_tests`.
+pub struct TestCase {
+    // Module path of the test, which ends with its name
+    pub path: &'static str,
+    pub function: fn(),
+}
+
+// Declares a kernel test. It runs after the kernel is fully initialized when booted with the
+// `test` option and fails by panicking.
+//
+//     kernel_test! {
+//         fn heap_grows() {
+//             ...
+//         }
+//     }
+macro_rules! kernel_test {
+    (fn $name:ident() $body:block) => {
+        #[allow(dead_code)]
+        mod $name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #[used]
+            #[link_section = ".kernel_tests"]
+            static TEST_CASE: $crate::ktest::TestCase = $crate::ktest::TestCase {
+                path: module_path!(),
+                function: run,
+            };
+
+            fn run() $body
+        }
+    };
+}
+
+#[cfg(not(test))]
+extern {
+    // Defined by the linker script around `.kernel_tests`
+    static __kernel_tests_start: u8;
+    static __kernel_tests_end: u8;
+}
+
+// Index of the running test plus one, zero when no test runs
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(not(test))]
+fn tests() -> &'static [TestCase] {
+    use core::{mem, slice};
+
+    unsafe {
+        let start = &__kernel_tests_start as *const u8 as usize;
+        let end = &__kernel_tests_end as *const u8 as usize;
+        slice::from_raw_parts(start as *const TestCase, (end - start) / mem::size_of::<TestCase>())
+    }
+}
+
+// Host builds don't use the linker script. They only compile the kernel tests.
+#[cfg(test)]
+fn tests() -> &'static [TestCase] {
+    &[]
+}
+
+// The console macros are defined after this module
+fn print(args: fmt::Arguments) {
+    ::io::term::kprint(args);
+}
+
+// `module_path!()` without the crate name
+fn name(test: &TestCase) -> &'static str {
+    match test.path.find("::") {
+        Some(index) => &test.path[index + 2..],
+        None => test.path,
+    }
+}
+
+// Runs every kernel test and exits QEMU with the result. A failing test panics, which ends the
+// run through `fail`.
+pub fn run() -> ! {
+    let tests = tests();
+    print(format_args!("\nrunning {} kernel tests\n", tests.len()));
+    for (index, test) in tests.iter().enumerate() {
+        print(format_args!("test {} ... ", name(test)));
+        CURRENT.store(index + 1, Ordering::SeqCst);
+        (test.function)();
+        CURRENT.store(0, Ordering::SeqCst);
+        print(format_args!("ok\n"));
+    }
+    print(format_args!("\ntest result: ok. {} passed\n", tests.len()));
+    power::qemu_exit(EXIT_SUCCESS)
+}
+
+// Called after a panic or fatal exception in test mode. Anything going wrong counts as a failure,
+// also during boot.
+pub fn fail() -> ! {
+    match CURRENT.load(Ordering::SeqCst) {
+        0 => print(format_args!("\ntest result: FAILED. the kernel panicked outside of a test\n")),
+        current => print(format_args!("\ntest {} FAILED\n", name(&tests()[current - 1]))),
+    }
+    power::qemu_exit(EXIT_FAILURE)
+}
@@ -1,15 +1,22 @@
 #![feature(abi_x86_interrupt)]
 #![feature(alloc)]
 #![feature(allocator_api)]
+#![feature(asm)]
 #![feature(global_allocator)]
 #![feature(lang_items)]
 #![feature(unique)]
+#![feature(used)]
 #![feature(const_fn)]
-#![no_std]
+// `cargo test` builds the kernel for the host with std to unit test its pure parts. What can't
+// exist there, like the panic handler and the scheduler, is left out. The kernel tests are
+// compiled but not run.
+#![cfg_attr(not(test), no_std)]
 
 #[allow(unused_imports)]
 #[macro_use]
 extern crate alloc;
+#[cfg(test)]
+extern crate core;
 extern crate bitfield;
 extern crate bit_field;
 #[macro_use]
@@ -20,29 +27,66 @@ extern crate multiboot2;
 #[macro_use]
 extern crate once;
 extern crate raw_cpuid;
+#[cfg(not(test))]
 extern crate rlibc;
 extern crate spin;
 extern crate volatile;
 extern crate x86_64;
 
+#[macro_use]
+mod log;
+#[macro_use]
+mod ktest;
 #[macro_use]
 mod io;
+mod acpi;
+mod backtrace;
+mod cmdline;
+mod elf;
 mod interrupts;
 mod memory;
+mod power;
+#[cfg(test)]
+mod test_util;
+// Context switches need the assembly, which host builds don't link
+#[cfg(not(test))]
+mod thread;
+mod time;
 
-#[no_mangle]
+#[cfg_attr(not(test), no_mangle)]
 pub extern fn rust_main(multiboot_info: usize) {
     io::vga::text_buffer::clear_screen();
 
     let boot_info = unsafe {
         multiboot2::load(multiboot_info)
     };
-    let mut memory_controller = memory::init(boot_info);
+    let command_line = cmdline::init(boot_info);
+    cmdline::apply_early(command_line);
+    memory::init(boot_info);
 
     unsafe {
         HEAP_ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE)
     }
-    interrupts::init(&mut memory_controller);
+    HEAP_ALLOCATOR.lock().set_growth(heap_limit(command_line.args().heap_max), memory::grow_heap);
+    memory::with_controller(|controller| backtrace::init(boot_info, controller));
+    cmdline::report(command_line);
+    memory::with_controller(|controller| acpi::init(boot_info, controller));
+    if command_line.args().acpi_dump {
+        acpi::dump();
+    }
+    memory::with_controller(interrupts::init);
+    memory::with_controller(time::init);
+    #[cfg(not(test))]
+    thread::init();
+    io::serial::enable_interrupts();
+    io::serial::report();
+
+    if command_line.args().test {
+        ktest::run();
+    }
+    if command_line.args().heap_report {
+        print_heap_report();
+    }
 
     kprintln!("It did not crash!");
 
@@ -62,34 +106,114 @@ pub extern fn rust_main(multiboot_info: usize) {
         kprint!("{}BLUE", green.to_escaped_string());
     }
 
-    let com1 = &mut io::serial::COM1.lock();
+    #[cfg(not(test))]
+    thread::spawn(echo_serial);
+    #[cfg(not(test))]
+    thread::exit();
+}
+
+// Echoes what arrives on COM1 to the console
+#[cfg(not(test))]
+fn echo_serial() {
+    let com1 = match *io::serial::COM1 {
+        Ok(ref com1) => com1,
+        Err(_) => return,
+    };
     loop {
-        match com1.read_byte() {
-            Some(b) => {
-                com1.write_byte_sync(b);
-                kprint!("{}", b as char);
-            },
-            None => {}
+        // The console echoes to COM1 as well, so don't hold on to it while waiting
+        let b = com1.lock().read_byte();
+        match b {
+            Some(b) => kprint!("{}", b as char),
+            None => thread::sleep(time::Duration::from_millis(10)),
         }
     }
 }
 
 pub const HEAP_START: usize = 0o_000_001_000_000_0000;
 pub const HEAP_SIZE: usize = 100 * 1024;
+// The heap maps more pages on demand until it reaches this size
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+// The size the heap may grow to given the `heap_max` option. Only `HEAP_MAX_SIZE` bytes are
+// reserved for the heap, and the initial heap can't shrink.
+fn heap_limit(requested: Option<usize>) -> usize {
+    match requested {
+        Some(size) if size < HEAP_SIZE => {
+            warn!("heap_max={:#x} is below the initial heap size, using {:#x}", size, HEAP_SIZE);
+            HEAP_SIZE
+        },
+        Some(size) if size > HEAP_MAX_SIZE => {
+            warn!("heap_max={:#x} exceeds the heap area, using {:#x}", size, HEAP_MAX_SIZE);
+            HEAP_MAX_SIZE
+        },
+        Some(size) => size,
+        None => HEAP_MAX_SIZE,
+    }
+}
+
+#[cfg(not(feature = "slab_allocator"))]
+use memory::heap_allocator::linked_list_allocator::LockedHeap as KernelHeap;
+#[cfg(feature = "slab_allocator")]
+use memory::heap_allocator::slab_allocator::LockedSlabHeap as KernelHeap;
+#[cfg_attr(not(test), global_allocator)]
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap::empty();
 
-use memory::heap_allocator::linked_list_allocator::LockedHeap;
-#[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+// Prints the heap statistics and a map of its used and free memory to the console. They are
+// copied while the heap is locked and printed afterwards, since printing may allocate. Like the
+// allocator, it holds the lock with interrupts disabled so the scheduler can still allocate.
+pub fn print_heap_report() {
+    use interrupts::without_interrupts;
 
+    #[cfg(feature = "slab_allocator")]
+    let (slab_statistics, statistics, map) = without_interrupts(|| {
+        let heap = HEAP_ALLOCATOR.lock();
+        (heap.statistics(), heap.backing_heap().statistics(), heap.backing_heap().fragmentation_map())
+    });
+    #[cfg(not(feature = "slab_allocator"))]
+    let (statistics, map) = without_interrupts(|| {
+        let heap = HEAP_ALLOCATOR.lock();
+        (heap.statistics(), heap.fragmentation_map())
+    });
 
+    #[cfg(feature = "slab_allocator")]
+    kprintln!("{}", slab_statistics);
+    kprintln!("{}", statistics);
+    kprintln!("{}", map);
+}
+
+#[cfg(not(test))]
 #[lang = "eh_personality"]
 #[no_mangle]
 pub extern fn eh_personality() {}
 
+#[cfg(not(test))]
 #[lang = "panic_fmt"]
 #[no_mangle]
 pub extern fn panic_fmt(fmt: core::fmt::Arguments, file: &'static str, line: u32) -> ! {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static PANICS: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe {
+        x86_64::instructions::interrupts::disable();
+    }
+    match PANICS.fetch_add(1, Ordering::SeqCst) {
+        0 => {},
+        // Printing the first panic panicked, so only print the bare message
+        1 => {
+            unsafe { io::term::console::take_over() };
+            kprintln!("\n\nPANIC while panicking in {} at line {}:", file, line);
+            kprintln!("  {}", fmt);
+            power::halt_forever()
+        },
+        // Even that failed, give up
+        _ => power::halt_forever(),
+    }
+
+    unsafe { io::term::console::take_over() };
+    log::panic_dump();
     kprintln!("\n\nPANIC in {} at line {}:", file, line);
     kprintln!("  {}", fmt);
-    loop {}
+    backtrace::print();
+    power::after_panic()
 }
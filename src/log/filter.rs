@@ -0,0 +1,114 @@
+use core::fmt;
+use core::str;
+
+use super::{Level, LevelFilter};
+
+const MAX_DIRECTIVES: usize = 16;
+const MAX_TARGET_LENGTH: usize = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterError {
+    InvalidLevel,
+    TargetTooLong,
+    TooManyTargets,
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            FilterError::InvalidLevel => "invalid log level",
+            FilterError::TargetTooLong => "log target name too long",
+            FilterError::TooManyTargets => "too many log targets",
+        })
+    }
+}
+
+// Maximum level for a target and the modules below it. The target is copied since the filter
+// outlives the string it was parsed from.
+#[derive(Clone, Copy)]
+struct Directive {
+    target: [u8; MAX_TARGET_LENGTH],
+    target_length: usize,
+    level: LevelFilter,
+}
+
+impl Directive {
+    fn target(&self) -> &str {
+        str::from_utf8(&self.target[..self.target_length]).unwrap()
+    }
+
+    fn matches(&self, target: &str) -> bool {
+        let prefix = self.target();
+        target.starts_with(prefix)
+            && (target.len() == prefix.len() || target[prefix.len()..].starts_with("::"))
+    }
+}
+
+// Which messages get logged. The most specific directive for a target wins, targets without one
+// use the default level.
+#[derive(Clone, Copy)]
+pub struct Filter {
+    default: LevelFilter,
+    directives: [Option<Directive>; MAX_DIRECTIVES],
+}
+
+#[allow(dead_code)]
+impl Filter {
+    pub const fn new(default: LevelFilter) -> Filter {
+        Filter {
+            default,
+            directives: [None; MAX_DIRECTIVES],
+        }
+    }
+
+    // Parses comma separated directives like `info,memory=debug,acpi::madt=trace`. A bare level
+    // sets the default.
+    pub fn parse(spec: &str) -> Result<Filter, FilterError> {
+        let mut filter = Filter::new(LevelFilter::Info);
+        for directive in spec.split(',').map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            let mut parts = directive.splitn(2, '=');
+            let first = parts.next().unwrap();
+            match parts.next() {
+                Some(level) => {
+                    let level = LevelFilter::parse(level).ok_or(FilterError::InvalidLevel)?;
+                    filter.add(first, level)?;
+                },
+                None => {
+                    filter.default = LevelFilter::parse(first).ok_or(FilterError::InvalidLevel)?;
+                },
+            }
+        }
+        Ok(filter)
+    }
+
+    pub fn add(&mut self, target: &str, level: LevelFilter) -> Result<(), FilterError> {
+        if target.len() > MAX_TARGET_LENGTH {
+            return Err(FilterError::TargetTooLong);
+        }
+        let mut directive = Directive {
+            target: [0; MAX_TARGET_LENGTH],
+            target_length: target.len(),
+            level,
+        };
+        directive.target[..target.len()].copy_from_slice(target.as_bytes());
+
+        let slot = self.directives.iter_mut()
+            .find(|slot| slot.map_or(true, |existing| existing.target() == target))
+            .ok_or(FilterError::TooManyTargets)?;
+        *slot = Some(directive);
+        Ok(())
+    }
+
+    pub fn set_default(&mut self, level: LevelFilter) {
+        self.default = level;
+    }
+
+    pub fn enabled(&self, level: Level, target: &str) -> bool {
+        let max_level = self.directives.iter()
+            .filter_map(|directive| directive.as_ref())
+            .filter(|directive| directive.matches(target))
+            .max_by_key(|directive| directive.target_length)
+            .map_or(self.default, |directive| directive.level);
+        level as usize <= max_level as usize
+    }
+}
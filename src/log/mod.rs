@@ -0,0 +1,214 @@
+use core::fmt;
+use core::str;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, Once};
+
+use interrupts::without_interrupts;
+use io::term::ansi::{AnsiSequence, TextAttribute};
+// The console macros are declared after this module so the rest of `io` can log
+use io::term::{console, kprint};
+use io::term::ring_log::{self, RingLog};
+
+mod filter;
+
+pub use self::filter::{Filter, FilterError};
+
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => ({
+        let level = $level;
+        if $crate::log::enabled(level, module_path!()) {
+            $crate::log::log(level, module_path!(), format_args!($($arg)*));
+        }
+    });
+}
+
+#[allow(unused_macros)]
+macro_rules! error {
+    ($($arg:tt)*) => (log!($crate::log::Level::Error, $($arg)*));
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => (log!($crate::log::Level::Warn, $($arg)*));
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => (log!($crate::log::Level::Info, $($arg)*));
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => (log!($crate::log::Level::Debug, $($arg)*));
+}
+
+#[allow(unused_macros)]
+macro_rules! trace {
+    ($($arg:tt)*) => (log!($crate::log::Level::Trace, $($arg)*));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+
+    fn color(&self) -> TextAttribute {
+        match *self {
+            Level::Error => TextAttribute::Red,
+            Level::Warn => TextAttribute::Yellow,
+            Level::Info => TextAttribute::Green,
+            Level::Debug => TextAttribute::Cyan,
+            Level::Trace => TextAttribute::Magenta,
+        }
+    }
+}
+
+// Most verbose level that is logged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LevelFilter {
+    Off = 0,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LevelFilter {
+    pub fn parse(s: &str) -> Option<LevelFilter> {
+        match s {
+            "off" => Some(LevelFilter::Off),
+            "error" => Some(LevelFilter::Error),
+            "warn" => Some(LevelFilter::Warn),
+            "info" => Some(LevelFilter::Info),
+            "debug" => Some(LevelFilter::Debug),
+            "trace" => Some(LevelFilter::Trace),
+            _ => None,
+        }
+    }
+}
+
+static FILTER: Mutex<Filter> = Mutex::new(Filter::new(LevelFilter::Info));
+// Nanoseconds since boot
+static CLOCK: Once<fn() -> u64> = Once::new();
+static DUMP_ON_PANIC: AtomicBool = AtomicBool::new(false);
+
+// Messages are timestamped with `clock` from now on
+#[allow(dead_code)]
+pub fn set_clock(clock: fn() -> u64) {
+    CLOCK.call_once(|| clock);
+}
+
+#[allow(dead_code)]
+pub fn set_filter(filter: Filter) {
+    without_interrupts(|| *FILTER.lock() = filter);
+}
+
+// Replaces the filter with one parsed from `spec`, see `Filter::parse`
+#[allow(dead_code)]
+pub fn set_filter_spec(spec: &str) -> Result<(), FilterError> {
+    set_filter(Filter::parse(spec)?);
+    Ok(())
+}
+
+#[allow(dead_code)]
+pub fn set_dump_on_panic(enabled: bool) {
+    DUMP_ON_PANIC.store(enabled, Ordering::Relaxed);
+}
+
+// `module_path!()` without the crate name, which is what filters refer to
+fn target(module_path: &str) -> &str {
+    match module_path.find("::") {
+        Some(index) => &module_path[index + 2..],
+        None => "",
+    }
+}
+
+pub fn enabled(level: Level, module_path: &str) -> bool {
+    // Interrupt handlers log too, so they must not find the filter locked
+    without_interrupts(|| FILTER.lock().enabled(level, target(module_path)))
+}
+
+struct Timestamp;
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match CLOCK.try() {
+            Some(clock) => {
+                let nanoseconds = clock();
+                write!(f, "[{:5}.{:06}] ", nanoseconds / 1_000_000_000, nanoseconds % 1_000_000_000 / 1000)
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+// Called by the logging macros for messages that passed the filter
+pub fn log(level: Level, module_path: &str, args: fmt::Arguments) {
+    let target = target(module_path);
+    let separator = if target.is_empty() { "" } else { ": " };
+
+    let color = AnsiSequence::SetGraphicsMode([Some(level.color()), None, None]);
+    let reset = AnsiSequence::SetGraphicsMode([Some(TextAttribute::Off), Some(TextAttribute::White), None]);
+    kprint(format_args!("{}{}{:5}{} {}{}{}\n", Timestamp, color, level.name(), reset, target, separator, args));
+}
+
+// Prints every message logged so far. They are kept by the console's `log` sink, so nothing is
+// kept while that is disabled, e.g. with `console=vga,serial`.
+#[allow(dead_code)]
+pub fn dump() {
+    without_interrupts(|| print_log(&ring_log::LOG.lock()));
+}
+
+// Dumps the log if that was asked for. The lock is only tried since the panic might have
+// happened while logging.
+#[cfg(not(test))]
+pub fn panic_dump() {
+    if !DUMP_ON_PANIC.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(log) = ring_log::LOG.try_lock() {
+        kprint(format_args!("\n--- kernel log ---\n"));
+        print_log(&log);
+        kprint(format_args!("--- end of kernel log ---\n"));
+    }
+}
+
+// The log is locked, so its sink is turned off while it's printed
+fn print_log(log: &RingLog) {
+    let enabled = console::is_enabled("log") == Some(true);
+    console::set_enabled("log", false);
+    let (older, newer) = log.contents();
+    print_bytes(older);
+    print_bytes(newer);
+    console::set_enabled("log", enabled);
+}
+
+// The log is cut at arbitrary bytes when it wraps around, so it might not be valid UTF-8
+fn print_bytes(mut bytes: &[u8]) {
+    while !bytes.is_empty() {
+        match str::from_utf8(bytes) {
+            Ok(s) => {
+                kprint(format_args!("{}", s));
+                break;
+            },
+            Err(error) => {
+                let valid = error.valid_up_to();
+                kprint(format_args!("{}?", unsafe { str::from_utf8_unchecked(&bytes[..valid]) }));
+                bytes = &bytes[valid + 1..];
+            },
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use core::fmt;
+
+use memory::{Frame, FrameAllocator, PAGE_SIZE};
+use multiboot2::MemoryAreaIter;
+
+// Largest block is 2^MAX_ORDER frames (4 MiB), big enough for a 2 MiB huge page
+pub const MAX_ORDER: usize = 10;
+const ORDER_COUNT: usize = MAX_ORDER + 1;
+
+// Highest physical address that is tracked. Frames above it are never handed out
+const MAX_PHYSICAL_MEMORY: usize = 4 * 1024 * 1024 * 1024;
+const MAX_FRAMES: usize = MAX_PHYSICAL_MEMORY / PAGE_SIZE;
+const BITS_PER_WORD: usize = 64;
+// Order `n` needs `MAX_FRAMES >> n` bits, so all orders together need less than twice as
+// many bits as order 0
+const BITMAP_WORDS: usize = 2 * MAX_FRAMES / BITS_PER_WORD;
+
+// One free bitmap per order, stored back to back. A set bit means the block is free.
+// It lives in .bss so it stays mapped after `remap_the_kernel`.
+static mut FREE_BITMAPS: [u64; BITMAP_WORDS] = [0; BITMAP_WORDS];
+
+pub struct BuddyFrameAllocator {
+    bitmaps: &'static mut [u64; BITMAP_WORDS],
+    // Word offset of each order's bitmap in `bitmaps`
+    offsets: [usize; ORDER_COUNT],
+    // No word below this index has a free block of that order
+    next_free_word: [usize; ORDER_COUNT],
+    free_blocks: [usize; ORDER_COUNT],
+}
+
+#[allow(dead_code)]
+impl BuddyFrameAllocator {
+    pub fn new(kernel_start: usize, kernel_end: usize, multiboot_start: usize, multiboot_end: usize, memory_areas: MemoryAreaIter) -> BuddyFrameAllocator {
+        assert_has_not_been_called!("Only one BuddyFrameAllocator may own the free bitmaps!");
+
+        let mut allocator = BuddyFrameAllocator::with_bitmaps(unsafe { &mut FREE_BITMAPS });
+        // still used by the kernel or the multiboot information
+        let reserved = [
+            (Frame::containing_address(kernel_start), Frame::containing_address(kernel_end)),
+            (Frame::containing_address(multiboot_start), Frame::containing_address(multiboot_end)),
+        ];
+        for area in memory_areas {
+            allocator.add_area(area.base_addr as usize, (area.base_addr + area.length) as usize, &reserved);
+        }
+
+        allocator
+    }
+
+    // An allocator without any free frames that keeps its free bitmaps in `bitmaps`, which must be
+    // zeroed
+    fn with_bitmaps(bitmaps: &'static mut [u64; BITMAP_WORDS]) -> BuddyFrameAllocator {
+        let mut offsets = [0; ORDER_COUNT];
+        for order in 1..ORDER_COUNT {
+            offsets[order] = offsets[order - 1] + Self::words_for_order(order - 1);
+        }
+
+        BuddyFrameAllocator {
+            bitmaps,
+            offsets,
+            next_free_word: [0; ORDER_COUNT],
+            free_blocks: [0; ORDER_COUNT],
+        }
+    }
+
+    // Frees the usable memory area `[start, end)` except for the `reserved` frame ranges. Only
+    // frames that are completely inside the area are usable.
+    fn add_area(&mut self, start: usize, end: usize, reserved: &[(Frame, Frame)]) {
+        let start_frame = Frame::containing_address(start + PAGE_SIZE - 1);
+        let end_frame = Frame::containing_address(end);
+
+        for number in start_frame.number..end_frame.number {
+            if number >= MAX_FRAMES {
+                break;
+            }
+            let frame = Frame { number };
+            if reserved.iter().any(|&(ref first, ref last)| frame >= *first && frame <= *last) {
+                continue;
+            }
+            // Freeing every frame merges them into the biggest possible blocks
+            self.deallocate_frames(frame, 0);
+        }
+    }
+
+    // Allocates 2^order physically contiguous frames. The first frame is aligned to the size
+    // of the block.
+    pub fn allocate_frames(&mut self, order: usize) -> Option<Frame> {
+        assert!(order <= MAX_ORDER, "order {} is bigger than the maximum of {}", order, MAX_ORDER);
+
+        for current_order in order..ORDER_COUNT {
+            if let Some(mut index) = self.take_free_block(current_order) {
+                // Split the block until it has the requested size. The upper half of each
+                // split goes back to the free bitmap of the lower order
+                for lower_order in (order..current_order).rev() {
+                    index <<= 1;
+                    self.set_free(lower_order, index + 1);
+                }
+                return Some(Frame { number: index << order });
+            }
+        }
+
+        // No block big enough left...
+        None
+    }
+
+    // Frees a block returned by `allocate_frames` with the same order. The block is merged
+    // with its buddy as long as the buddy is free as well.
+    pub fn deallocate_frames(&mut self, frame: Frame, order: usize) {
+        assert!(order <= MAX_ORDER, "order {} is bigger than the maximum of {}", order, MAX_ORDER);
+        assert!(frame.number < MAX_FRAMES,
+            "frame {:#x} was not allocated by this allocator", frame.start_address());
+        assert_eq!(frame.number % (1 << order), 0,
+            "frame {:#x} is not aligned to a block of order {}", frame.start_address(), order);
+
+        // A freed block may have been merged into a bigger free block since
+        assert!((order..ORDER_COUNT).all(|order| !self.is_free(order, frame.number >> order)),
+            "frame {:#x} was freed twice", frame.start_address());
+
+        let mut index = frame.number >> order;
+        let mut order = order;
+
+        while order < MAX_ORDER && self.is_free(order, index ^ 1) {
+            self.clear_free(order, index ^ 1);
+            index >>= 1;
+            order += 1;
+        }
+        self.set_free(order, index);
+    }
+
+    // Takes every frame in `[start, end]` out of the free blocks so it will never be handed out
+    pub fn reserve_range(&mut self, start: Frame, end: Frame) {
+        for frame in Frame::range_inclusive(start, end) {
+            if frame.number < MAX_FRAMES {
+                self.take_frame(frame);
+            }
+        }
+    }
+
+    // Removes `frame` from the free block containing it, if there is one. The block is split
+    // and every part except the frame stays free.
+    fn take_frame(&mut self, frame: Frame) {
+        for order in 0..ORDER_COUNT {
+            if self.is_free(order, frame.number >> order) {
+                self.clear_free(order, frame.number >> order);
+                for lower_order in (0..order).rev() {
+                    self.set_free(lower_order, (frame.number >> lower_order) ^ 1);
+                }
+                return;
+            }
+        }
+    }
+
+    pub fn statistics(&self) -> BuddyStatistics {
+        BuddyStatistics {
+            free_blocks: self.free_blocks,
+        }
+    }
+
+    fn words_for_order(order: usize) -> usize {
+        (MAX_FRAMES >> order) / BITS_PER_WORD
+    }
+
+    // Finds a free block of exactly the given order and removes it from the free bitmap
+    fn take_free_block(&mut self, order: usize) -> Option<usize> {
+        if self.free_blocks[order] == 0 {
+            return None;
+        }
+
+        let offset = self.offsets[order];
+        for word_index in self.next_free_word[order]..Self::words_for_order(order) {
+            let word = self.bitmaps[offset + word_index];
+            if word != 0 {
+                let index = word_index * BITS_PER_WORD + word.trailing_zeros() as usize;
+                self.next_free_word[order] = word_index;
+                self.clear_free(order, index);
+                return Some(index);
+            }
+        }
+        unreachable!("free block count for order {} is out of sync with its bitmap", order);
+    }
+
+    fn is_free(&self, order: usize, index: usize) -> bool {
+        let word = self.offsets[order] + index / BITS_PER_WORD;
+        self.bitmaps[word] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set_free(&mut self, order: usize, index: usize) {
+        let word_index = index / BITS_PER_WORD;
+        self.bitmaps[self.offsets[order] + word_index] |= 1 << (index % BITS_PER_WORD);
+        self.free_blocks[order] += 1;
+        if word_index < self.next_free_word[order] {
+            self.next_free_word[order] = word_index;
+        }
+    }
+
+    fn clear_free(&mut self, order: usize, index: usize) {
+        let word = self.offsets[order] + index / BITS_PER_WORD;
+        self.bitmaps[word] &= !(1 << (index % BITS_PER_WORD));
+        self.free_blocks[order] -= 1;
+    }
+}
+
+impl FrameAllocator for BuddyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<Frame> {
+        self.allocate_frames(0)
+    }
+
+    fn deallocate_frame(&mut self, frame: Frame) {
+        self.deallocate_frames(frame, 0)
+    }
+}
+
+// Snapshot of the free blocks of a `BuddyFrameAllocator`
+#[derive(Debug, Clone, Copy)]
+pub struct BuddyStatistics {
+    free_blocks: [usize; ORDER_COUNT],
+}
+
+#[allow(dead_code)]
+impl BuddyStatistics {
+    pub fn free_blocks(&self, order: usize) -> usize {
+        self.free_blocks[order]
+    }
+
+    pub fn free_frames(&self, order: usize) -> usize {
+        self.free_blocks[order] << order
+    }
+
+    pub fn total_free_frames(&self) -> usize {
+        (0..ORDER_COUNT).map(|order| self.free_frames(order)).sum()
+    }
+}
+
+impl fmt::Display for BuddyStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "order  block size  free blocks  free frames")?;
+        for order in 0..ORDER_COUNT {
+            writeln!(f, "{:>5}  {:>8}K  {:>11}  {:>11}",
+                order, (PAGE_SIZE << order) / 1024, self.free_blocks(order), self.free_frames(order))?;
+        }
+        write!(f, "total free frames: {}", self.total_free_frames())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use memory::{Frame, PAGE_SIZE};
+    use super::{BuddyFrameAllocator, BITMAP_WORDS, MAX_ORDER};
+
+    fn frame(number: usize) -> Frame {
+        Frame { number }
+    }
+
+    // An allocator with its own bitmaps and no free frames. The bitmaps are leaked since the
+    // allocator needs them forever.
+    fn empty() -> BuddyFrameAllocator {
+        BuddyFrameAllocator::with_bitmaps(unsafe { &mut *Box::into_raw(Box::new([0; BITMAP_WORDS])) })
+    }
+
+    // An allocator whose memory map has a single area with the first `frames` frames
+    fn allocator(frames: usize) -> BuddyFrameAllocator {
+        let mut allocator = empty();
+        allocator.add_area(0, frames * PAGE_SIZE, &[]);
+        allocator
+    }
+
+    #[test]
+    fn areas_merge_into_the_biggest_blocks() {
+        let allocator = allocator(16 + 4 + 1);
+        let statistics = allocator.statistics();
+        assert_eq!(statistics.free_blocks(4), 1);
+        assert_eq!(statistics.free_blocks(2), 1);
+        assert_eq!(statistics.free_blocks(0), 1);
+        assert_eq!(statistics.total_free_frames(), 21);
+    }
+
+    #[test]
+    fn only_whole_frames_of_an_area_are_used() {
+        let mut allocator = empty();
+        allocator.add_area(100, 3 * PAGE_SIZE + 5, &[]);
+        assert_eq!(allocator.statistics().total_free_frames(), 2);
+        // Frames 1 and 2 are not buddies
+        assert_eq!(allocator.allocate_frames(1), None);
+        assert_eq!(allocator.allocate_frames(0), Some(frame(1)));
+        assert_eq!(allocator.allocate_frames(0), Some(frame(2)));
+    }
+
+    #[test]
+    fn reserved_ranges_are_not_freed() {
+        let mut allocator = empty();
+        allocator.add_area(0, 8 * PAGE_SIZE, &[(frame(2), frame(3))]);
+        assert_eq!(allocator.statistics().total_free_frames(), 6);
+        while let Some(allocated) = allocator.allocate_frames(0) {
+            assert!(allocated != frame(2) && allocated != frame(3));
+        }
+    }
+
+    #[test]
+    fn allocations_split_bigger_blocks() {
+        let mut allocator = allocator(16);
+        assert_eq!(allocator.allocate_frames(0), Some(frame(0)));
+
+        // The upper halves of every split stay free
+        let statistics = allocator.statistics();
+        for order in 0..4 {
+            assert_eq!(statistics.free_blocks(order), 1);
+        }
+        assert_eq!(statistics.free_blocks(4), 0);
+        assert_eq!(allocator.allocate_frames(2), Some(frame(4)));
+        assert_eq!(allocator.allocate_frames(0), Some(frame(1)));
+    }
+
+    #[test]
+    fn blocks_are_aligned_to_their_size() {
+        let mut allocator = allocator(1 << MAX_ORDER);
+        assert_eq!(allocator.allocate_frames(0), Some(frame(0)));
+        for order in 1..MAX_ORDER {
+            let block = allocator.allocate_frames(order).expect("out of frames");
+            assert_eq!(block.number % (1 << order), 0);
+        }
+        assert_eq!(allocator.allocate_frames(MAX_ORDER), None);
+    }
+
+    #[test]
+    fn freed_buddies_merge_across_orders() {
+        let mut allocator = allocator(16);
+        let frames: Vec<Frame> = (0..16).map(|_| allocator.allocate_frames(0).unwrap()).collect();
+        assert_eq!(allocator.allocate_frames(0), None);
+
+        for frame in frames {
+            allocator.deallocate_frames(frame, 0);
+        }
+        let statistics = allocator.statistics();
+        assert_eq!(statistics.free_blocks(4), 1);
+        assert_eq!(statistics.total_free_frames(), 16);
+        assert_eq!(allocator.allocate_frames(4), Some(frame(0)));
+    }
+
+    #[test]
+    fn reserved_frames_are_split_out_of_free_blocks() {
+        let mut allocator = allocator(16);
+        allocator.reserve_range(frame(5), frame(6));
+        // Already reserved frames are skipped
+        allocator.reserve_range(frame(6), frame(6));
+        assert_eq!(allocator.statistics().total_free_frames(), 14);
+        assert_eq!(allocator.statistics().free_blocks(3), 1);
+
+        let mut allocated = Vec::new();
+        while let Some(frame) = allocator.allocate_frames(0) {
+            allocated.push(frame.number);
+        }
+        allocated.sort();
+        assert_eq!(allocated, [0, 1, 2, 3, 4, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    #[should_panic(expected = "freed twice")]
+    fn double_frees_are_caught() {
+        let mut allocator = allocator(16);
+        let block = allocator.allocate_frames(1).unwrap();
+        allocator.deallocate_frames(frame(block.number), 1);
+        allocator.deallocate_frames(block, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "freed twice")]
+    fn frees_of_merged_blocks_are_caught() {
+        let mut allocator = allocator(16);
+        let block = allocator.allocate_frames(0).unwrap();
+        allocator.deallocate_frames(block, 0);
+        // Frame 1 was never allocated, it's part of the merged block
+        allocator.deallocate_frames(frame(1), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "not aligned")]
+    fn misaligned_blocks_are_rejected() {
+        let mut allocator = allocator(16);
+        allocator.deallocate_frames(frame(1), 1);
+    }
+}
@@ -0,0 +1,110 @@
+use core::mem::{align_of, size_of};
+use core::ptr;
+use alloc::allocator::Layout;
+
+use memory::align_up;
+
+// Number of canary bytes in front of and behind every allocation.
+const CANARY_SIZE: usize = 16;
+const CANARY_BYTE: u8 = 0xca;
+// Freed memory is filled with this pattern so use after free is easy to spot.
+const POISON_BYTE: u8 = 0xde;
+
+const ALLOCATED_MAGIC: usize = 0xa110_ca7e_d0b1_0c55;
+const FREED_MAGIC: usize = 0xf4ee_d0b1_0c55_dead;
+
+// Stored right in front of every allocation to recognize pointers the heap handed out.
+#[repr(C)]
+struct Header {
+    magic: usize,
+    size: usize,
+    align: usize,
+}
+
+// Layout of an allocation in debug mode:
+//
+// `[front canary][Header][allocation][back canary]`
+//
+// The front canary is at least `CANARY_SIZE` bytes long but grows so that the allocation keeps
+// its alignment. A hole written over a freed block only overwrites the front canary, so the
+// header survives and double frees can be detected.
+pub struct GuardedLayout {
+    front: usize,
+    layout: Layout,
+}
+
+impl GuardedLayout {
+    pub fn new(layout: &Layout) -> GuardedLayout {
+        let align = if layout.align() > align_of::<Header>() { layout.align() } else { align_of::<Header>() };
+        GuardedLayout {
+            front: align_up(CANARY_SIZE + size_of::<Header>(), align),
+            layout: layout.clone(),
+        }
+    }
+
+    // The layout of the whole block that has to be allocated from the hole list.
+    pub fn block_layout(&self) -> Layout {
+        let align = if self.layout.align() > align_of::<Header>() { self.layout.align() } else { align_of::<Header>() };
+        Layout::from_size_align(self.front + self.layout.size() + CANARY_SIZE, align).unwrap()
+    }
+
+    // Writes the canaries and the header into a newly allocated block and returns the pointer
+    // to hand out.
+    pub unsafe fn fill(&self, block: *mut u8) -> *mut u8 {
+        let ptr = block.offset(self.front as isize);
+        let header = ptr.offset(-(size_of::<Header>() as isize));
+
+        ptr::write_bytes(block, CANARY_BYTE, header as usize - block as usize);
+        ptr::write(header as *mut Header, Header {
+            magic: ALLOCATED_MAGIC,
+            size: self.layout.size(),
+            align: self.layout.align(),
+        });
+        ptr::write_bytes(ptr.offset(self.layout.size() as isize), CANARY_BYTE, CANARY_SIZE);
+        ptr
+    }
+}
+
+// Checks that `ptr` is a live allocation with the given layout and that its canaries are
+// intact, then poisons it. Returns the block and its layout so it can be given back to the
+// hole list. Panics with the allocation's address and layout if any check fails.
+pub unsafe fn release(ptr: *mut u8, layout: Layout) -> (*mut u8, Layout) {
+    let header = &mut *(ptr.offset(-(size_of::<Header>() as isize)) as *mut Header);
+
+    match header.magic {
+        ALLOCATED_MAGIC => {},
+        FREED_MAGIC => panic!("double free of {:#x} with {:?}", ptr as usize, layout),
+        _ => panic!("free of {:#x} with {:?}, which was not returned by allocate_first_fit",
+            ptr as usize, layout),
+    }
+    if header.size != layout.size() || header.align != layout.align() {
+        panic!("free of {:#x} with {:?}, but it was allocated with size {} and align {}",
+            ptr as usize, layout, header.size, header.align);
+    }
+
+    let guarded = GuardedLayout::new(&layout);
+    let block = ptr.offset(-(guarded.front as isize));
+    let front_canary = ptr.offset(-(size_of::<Header>() as isize));
+    if !is_filled(block, front_canary as usize - block as usize, CANARY_BYTE) {
+        panic!("heap corruption: memory in front of {:#x} with {:?} was overwritten",
+            ptr as usize, layout);
+    }
+    if !is_filled(ptr.offset(layout.size() as isize), CANARY_SIZE, CANARY_BYTE) {
+        panic!("heap corruption: memory behind {:#x} with {:?} was overwritten",
+            ptr as usize, layout);
+    }
+
+    ptr::write_bytes(ptr, POISON_BYTE, layout.size());
+    header.magic = FREED_MAGIC;
+
+    (block, guarded.block_layout())
+}
+
+// Returns the number of bytes in front of every allocation that belong to its block.
+pub fn front_size(layout: &Layout) -> usize {
+    GuardedLayout::new(layout).front
+}
+
+unsafe fn is_filled(start: *const u8, len: usize, byte: u8) -> bool {
+    (0..len).all(|i| *start.offset(i as isize) == byte)
+}
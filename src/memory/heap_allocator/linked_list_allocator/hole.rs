@@ -1,4 +1,5 @@
 use core::ptr::Unique;
+use core::marker::PhantomData;
 use core::mem::{self, size_of};
 use alloc::allocator::{Layout, AllocErr};
 
@@ -78,6 +79,14 @@ impl HoleList {
         size_of::<usize>() * 2
     }
 
+    /// Returns an iterator over the `(address, size)` of every hole, sorted by address.
+    pub fn holes(&self) -> HoleIter {
+        HoleIter {
+            current: self.first.next.as_ref().map(|hole| hole.as_ptr() as *const Hole),
+            list: PhantomData,
+        }
+    }
+
     /// Returns information about the first hole for test purposes.
     #[cfg(test)]
     pub fn first_hole(&self) -> Option<(usize, usize)> {
@@ -87,6 +96,24 @@ impl HoleList {
     }
 }
 
+/// Iterator over the holes of a `HoleList`, see `HoleList::holes`.
+pub struct HoleIter<'a> {
+    current: Option<*const Hole>,
+    list: PhantomData<&'a HoleList>,
+}
+
+impl<'a> Iterator for HoleIter<'a> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.current.map(|hole| {
+            let hole = unsafe { &*hole };
+            self.current = hole.next.as_ref().map(|next| next.as_ptr() as *const Hole);
+            (hole as *const _ as usize, hole.size)
+        })
+    }
+}
+
 /// A block containing free memory. It points to the next hole and thus forms a linked list.
 #[cfg(not(test))]
 pub struct Hole {
@@ -3,15 +3,30 @@ use core::ops::Deref;
 use alloc::allocator::{Alloc, Layout, AllocErr};
 use spin::Mutex;
 
-use memory::align_up;
+use memory::{align_up, PAGE_SIZE};
+use super::with_locked;
 use self::hole::{Hole, HoleList};
+use self::stats::Counters;
+pub use self::stats::{HeapStatistics, FragmentationMap};
 
+#[cfg(feature = "heap_debug")]
+mod debug;
 mod hole;
+mod stats;
+
+// Smallest amount the heap grows by at once so small allocations don't map pages one at a time
+const MIN_GROWTH: usize = 16 * PAGE_SIZE;
+
+// Makes `[top, top + size)` usable memory for the heap. Returns false if it could not.
+pub type HeapGrowFn = fn(top: usize, size: usize) -> bool;
 
 pub struct Heap {
     bottom: usize,
     size: usize,
     holes: HoleList,
+    max_size: usize,
+    grow: Option<HeapGrowFn>,
+    counters: Counters,
 }
 
 impl Heap {
@@ -21,6 +36,9 @@ impl Heap {
             bottom: 0,
             size: 0,
             holes: HoleList::empty(),
+            max_size: 0,
+            grow: None,
+            counters: Counters::new(),
         }
     }
 
@@ -30,6 +48,7 @@ impl Heap {
         self.bottom = heap_bottom;
         self.size = heap_size;
         self.holes = HoleList::new(heap_bottom, heap_size);
+        self.max_size = heap_size;
     }
 
     // Creates a new heap with the given bottom and size
@@ -40,12 +59,37 @@ impl Heap {
             bottom: heap_bottom,
             size: heap_size,
             holes: HoleList::new(heap_bottom, heap_size),
+            max_size: heap_size,
+            grow: None,
+            counters: Counters::new(),
         }
     }
 
+    // Lets the heap grow up to `max_size` bytes when it runs out of memory. `grow` is called
+    // to make the memory right above the current top usable before the heap is extended over it.
+    pub fn set_growth(&mut self, max_size: usize, grow: HeapGrowFn) {
+        assert!(max_size >= self.size, "the heap is already bigger than {:#x} bytes", max_size);
+        self.max_size = max_size;
+        self.grow = Some(grow);
+    }
+
     // Allocates a chunk of the given size with the given alignment
     // Returns a pointer to the beginning of that chunk if it was successful else `None`
+    #[cfg(not(feature = "heap_debug"))]
     pub fn allocate_first_fit(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        self.allocate_block(layout)
+    }
+
+    // In debug mode every allocation is surrounded by canaries, see `debug::GuardedLayout`
+    #[cfg(feature = "heap_debug")]
+    pub fn allocate_first_fit(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let guarded = debug::GuardedLayout::new(&layout);
+        let block = self.allocate_block(guarded.block_layout())
+            .map_err(|_| AllocErr::Exhausted { request: layout })?;
+        Ok(unsafe { guarded.fill(block) })
+    }
+
+    fn allocate_block(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
         let mut size = layout.size();
         if size < HoleList::min_size() {
             size = HoleList::min_size();
@@ -53,11 +97,74 @@ impl Heap {
         let size = align_up(size, mem::align_of::<Hole>());
         let layout = Layout::from_size_align(size, layout.align()).unwrap();
 
-        self.holes.allocate_first_fit(layout)
+        let ptr = self.holes.allocate_first_fit(layout)?;
+        self.counters.record_allocation(size);
+        Ok(ptr)
+    }
+
+    // Like `allocate_first_fit` but grows the heap and tries again if there is no big enough hole
+    pub fn allocate(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        match self.allocate_first_fit(layout.clone()) {
+            Err(err) => {
+                if self.try_grow(&layout) {
+                    self.allocate_first_fit(layout)
+                }
+                else {
+                    Err(err)
+                }
+            },
+            result => result,
+        }
+    }
+
+    // Grows the heap by enough to fit `layout` even if the top of the heap is in use
+    fn try_grow(&mut self, layout: &Layout) -> bool {
+        let grow = match self.grow {
+            Some(grow) => grow,
+            None => return false,
+        };
+
+        #[cfg(feature = "heap_debug")]
+        let layout = &debug::GuardedLayout::new(layout).block_layout();
+
+        let needed = layout.size() + layout.align() + HoleList::min_size();
+        let by = align_up(if needed < MIN_GROWTH { MIN_GROWTH } else { needed }, PAGE_SIZE);
+        let by = if self.size + by > self.max_size {
+            // Give it whatever is left below the ceiling
+            self.max_size - self.size
+        }
+        else {
+            by
+        };
+
+        if by < needed || !grow(self.top(), by) {
+            return false;
+        }
+        unsafe {
+            self.extend(by);
+        }
+        true
     }
 
     // Frees the given allocation. `ptr` must be a pointer returned by `allocate_first_fit`
+    #[cfg(not(feature = "heap_debug"))]
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        self.deallocate_block(ptr, layout);
+    }
+
+    // In debug mode this panics on double frees, foreign pointers and overwritten canaries
+    // instead of corrupting the hole list
+    #[cfg(feature = "heap_debug")]
     pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let addr = ptr as usize;
+        if addr < self.bottom + debug::front_size(&layout) || addr >= self.top() {
+            panic!("free of {:#x} with {:?}, which is outside of the heap", addr, layout);
+        }
+        let (block, block_layout) = debug::release(ptr, layout);
+        self.deallocate_block(block, block_layout);
+    }
+
+    unsafe fn deallocate_block(&mut self, ptr: *mut u8, layout: Layout) {
         let mut size = layout.size();
         if size < HoleList::min_size() {
             size = HoleList::min_size();
@@ -66,6 +173,7 @@ impl Heap {
         let layout = Layout::from_size_align(size, layout.align()).unwrap();
 
         self.holes.deallocate(ptr, layout);
+        self.counters.record_deallocation(size);
     }
 
     pub fn bottom(&self) -> usize {
@@ -80,6 +188,30 @@ impl Heap {
         self.bottom + self.size
     }
 
+    // Walks the hole list to take a snapshot of how the heap is used
+    pub fn statistics(&self) -> HeapStatistics {
+        let (free_bytes, holes, largest_hole) = self.holes.holes()
+            .fold((0, 0, 0), |(free, count, largest), (_, size)| {
+                (free + size, count + 1, if size > largest { size } else { largest })
+            });
+
+        HeapStatistics {
+            size: self.size,
+            allocated_bytes: self.counters.allocated_bytes,
+            peak_allocated_bytes: self.counters.peak_allocated_bytes,
+            free_bytes,
+            holes,
+            largest_hole,
+            live_allocations: self.counters.live_allocations,
+            total_allocations: self.counters.total_allocations,
+        }
+    }
+
+    // Map of used and free memory that can be printed with `kprintln!`
+    pub fn fragmentation_map(&self) -> FragmentationMap {
+        FragmentationMap::new(self)
+    }
+
     // Make sure the memory in that location is free!
     pub unsafe fn extend(&mut self, by: usize) {
         let top = self.top();
@@ -91,7 +223,7 @@ impl Heap {
 
 unsafe impl Alloc for Heap {
     unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
-        self.allocate_first_fit(layout)
+        self.allocate(layout)
     }
 
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
@@ -109,11 +241,7 @@ impl LockedHeap {
 
     // New heap with given bottom and size. Make sure the memory exists!
     pub unsafe fn new(heap_bottom: usize, heap_size: usize) -> LockedHeap {
-        LockedHeap(Mutex::new(Heap {
-            bottom: heap_bottom,
-            size: heap_size,
-            holes: HoleList::new(heap_bottom, heap_size),
-        }))
+        LockedHeap(Mutex::new(Heap::new(heap_bottom, heap_size)))
     }
 }
 
@@ -127,10 +255,141 @@ impl Deref for LockedHeap {
 
 unsafe impl<'a> Alloc for &'a LockedHeap {
     unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
-        self.0.lock().allocate_first_fit(layout)
+        with_locked(&self.0, |heap| heap.allocate(layout))
     }
 
     unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
-        self.0.lock().deallocate(ptr, layout);
+        with_locked(&self.0, |heap| heap.deallocate(ptr, layout));
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::size_of;
+    use alloc::allocator::Layout;
+
+    use test_util::XorShift;
+    use super::hole::HoleList;
+
+    const HEAP_SIZE: usize = 4096;
+
+    // The list lives in the memory it manages, so the memory must outlive it
+    fn new_list() -> (Vec<usize>, HoleList) {
+        let mut memory = vec![0usize; HEAP_SIZE / size_of::<usize>()];
+        let holes = unsafe { HoleList::new(memory.as_mut_ptr() as usize, HEAP_SIZE) };
+        (memory, holes)
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn new_list_is_one_hole() {
+        let (memory, holes) = new_list();
+        assert_eq!(holes.first_hole(), Some((memory.as_ptr() as usize, HEAP_SIZE)));
+        assert_eq!(holes.holes().count(), 1);
+    }
+
+    #[test]
+    fn allocations_come_from_the_front() {
+        let (memory, mut holes) = new_list();
+        let start = memory.as_ptr() as usize;
+
+        let ptr = holes.allocate_first_fit(layout(64, 8)).unwrap();
+        assert_eq!(ptr as usize, start);
+        assert_eq!(holes.first_hole(), Some((start + 64, HEAP_SIZE - 64)));
+    }
+
+    #[test]
+    fn freed_blocks_merge_with_their_neighbours() {
+        let (memory, mut holes) = new_list();
+
+        let a = holes.allocate_first_fit(layout(64, 8)).unwrap();
+        let b = holes.allocate_first_fit(layout(64, 8)).unwrap();
+        let c = holes.allocate_first_fit(layout(64, 8)).unwrap();
+        unsafe {
+            holes.deallocate(a, layout(64, 8));
+            holes.deallocate(c, layout(64, 8));
+            assert_eq!(holes.holes().count(), 2);
+            holes.deallocate(b, layout(64, 8));
+        }
+        assert_eq!(holes.first_hole(), Some((memory.as_ptr() as usize, HEAP_SIZE)));
+    }
+
+    #[test]
+    fn allocations_are_aligned() {
+        let (_memory, mut holes) = new_list();
+
+        holes.allocate_first_fit(layout(16, 8)).unwrap();
+        let ptr = holes.allocate_first_fit(layout(64, 256)).unwrap();
+        assert_eq!(ptr as usize % 256, 0);
+    }
+
+    #[test]
+    fn too_big_allocations_fail() {
+        let (_memory, mut holes) = new_list();
+
+        assert!(holes.allocate_first_fit(layout(HEAP_SIZE + 16, 8)).is_err());
+        assert!(holes.allocate_first_fit(layout(HEAP_SIZE, 8)).is_ok());
+        assert!(holes.allocate_first_fit(layout(16, 8)).is_err());
+    }
+
+    // Every byte is either in a hole or in exactly one live block, the holes are sorted and free
+    // blocks are merged
+    fn check_invariants(holes: &HoleList, start: usize, live: &[(usize, usize)]) {
+        let mut free = 0;
+        let mut previous_end = start;
+        for (address, size) in holes.holes() {
+            assert!(address >= previous_end, "holes overlap or are not sorted");
+            assert!(address > previous_end || previous_end == start, "adjacent holes were not merged");
+            assert!(size >= HoleList::min_size());
+            assert!(live.iter().all(|&(block, block_size)| block + block_size <= address || block >= address + size),
+                "hole {:#x} overlaps a live block", address);
+            free += size;
+            previous_end = address + size;
+        }
+        assert!(previous_end <= start + HEAP_SIZE);
+
+        for (i, &(block, size)) in live.iter().enumerate() {
+            assert!(block >= start && block + size <= start + HEAP_SIZE);
+            assert!(live[i + 1..].iter().all(|&(other, other_size)| block + size <= other || other + other_size <= block),
+                "block {:#x} overlaps another live block", block);
+        }
+        assert_eq!(free + live.iter().map(|&(_, size)| size).sum::<usize>(), HEAP_SIZE);
+    }
+
+    #[test]
+    fn random_allocations_keep_the_list_consistent() {
+        let mut rng = XorShift::new(0xdead_beef);
+        for _ in 0..50 {
+            let (memory, mut holes) = new_list();
+            let start = memory.as_ptr() as usize;
+            let mut live: Vec<(usize, usize)> = Vec::new();
+            let mut layouts: Vec<Layout> = Vec::new();
+
+            for _ in 0..200 {
+                if live.is_empty() || rng.below(3) != 0 {
+                    let size = (1 + rng.below(16)) * HoleList::min_size();
+                    let align = 1 << (3 + rng.below(5));
+                    if let Ok(ptr) = holes.allocate_first_fit(layout(size, align)) {
+                        assert_eq!(ptr as usize % align, 0);
+                        live.push((ptr as usize, size));
+                        layouts.push(layout(size, align));
+                    }
+                }
+                else {
+                    let index = rng.below(live.len());
+                    let (block, _) = live.swap_remove(index);
+                    unsafe { holes.deallocate(block as *mut u8, layouts.swap_remove(index)) };
+                }
+                check_invariants(&holes, start, &live);
+            }
+
+            for ((block, _), layout) in live.drain(..).zip(layouts.drain(..)) {
+                unsafe { holes.deallocate(block as *mut u8, layout) };
+            }
+            assert_eq!(holes.first_hole(), Some((start, HEAP_SIZE)));
+        }
+    }
+}
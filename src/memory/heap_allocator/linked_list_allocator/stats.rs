@@ -0,0 +1,162 @@
+use core::fmt;
+
+use super::Heap;
+
+// Number of cells in a fragmentation map and how many are printed per line
+const MAP_CELLS: usize = 256;
+const MAP_WIDTH: usize = 64;
+
+// Running totals a `Heap` keeps about its allocations.
+pub struct Counters {
+    pub allocated_bytes: usize,
+    pub peak_allocated_bytes: usize,
+    pub live_allocations: usize,
+    pub total_allocations: usize,
+}
+
+impl Counters {
+    pub const fn new() -> Counters {
+        Counters {
+            allocated_bytes: 0,
+            peak_allocated_bytes: 0,
+            live_allocations: 0,
+            total_allocations: 0,
+        }
+    }
+
+    pub fn record_allocation(&mut self, size: usize) {
+        self.allocated_bytes += size;
+        if self.allocated_bytes > self.peak_allocated_bytes {
+            self.peak_allocated_bytes = self.allocated_bytes;
+        }
+        self.live_allocations += 1;
+        self.total_allocations += 1;
+    }
+
+    pub fn record_deallocation(&mut self, size: usize) {
+        self.allocated_bytes -= size;
+        self.live_allocations -= 1;
+    }
+}
+
+// Snapshot of the usage of a `Heap`, see `Heap::statistics`.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStatistics {
+    // Current size of the heap in bytes
+    pub size: usize,
+    // Bytes handed out, including the rounding and padding of each allocation
+    pub allocated_bytes: usize,
+    // Highest value `allocated_bytes` ever had
+    pub peak_allocated_bytes: usize,
+    // Bytes in holes
+    pub free_bytes: usize,
+    pub holes: usize,
+    pub largest_hole: usize,
+    // Allocations that were not freed yet
+    pub live_allocations: usize,
+    // Allocations made since the heap was created
+    pub total_allocations: usize,
+}
+
+impl HeapStatistics {
+    // Percentage of free memory that is not part of the largest hole. 0 means all free memory
+    // is in one piece.
+    pub fn fragmentation(&self) -> usize {
+        if self.free_bytes == 0 {
+            0
+        }
+        else {
+            100 - self.largest_hole * 100 / self.free_bytes
+        }
+    }
+}
+
+impl fmt::Display for HeapStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "heap size:        {:#x}", self.size)?;
+        writeln!(f, "allocated bytes:  {:#x} (peak {:#x})", self.allocated_bytes, self.peak_allocated_bytes)?;
+        writeln!(f, "free bytes:       {:#x}", self.free_bytes)?;
+        writeln!(f, "holes:            {} (largest {:#x}, {}% fragmented)",
+            self.holes, self.largest_hole, self.fragmentation())?;
+        write!(f, "allocations:      {} live, {} total", self.live_allocations, self.total_allocations)
+    }
+}
+
+// Displays the heap as a grid of cells from bottom to top. Each cell stands for the same
+// number of bytes and shows `.` if it is free, `#` if it is in use and `+` if it is partly
+// free. Created by `Heap::fragmentation_map`, it's a snapshot that can be printed after the heap
+// was unlocked.
+#[derive(Clone, Copy)]
+pub struct FragmentationMap {
+    bottom: usize,
+    top: usize,
+    cell_size: usize,
+    cells: [u8; MAP_CELLS],
+    cell_count: usize,
+}
+
+impl FragmentationMap {
+    pub fn new(heap: &Heap) -> FragmentationMap {
+        let mut map = FragmentationMap {
+            bottom: heap.bottom(),
+            top: heap.top(),
+            cell_size: (heap.size() + MAP_CELLS - 1) / MAP_CELLS,
+            cells: [0; MAP_CELLS],
+            cell_count: 0,
+        };
+        if map.cell_size == 0 {
+            return map;
+        }
+
+        for cell in 0..MAP_CELLS {
+            let start = map.bottom + cell * map.cell_size;
+            let end = if start + map.cell_size < map.top { start + map.cell_size } else { map.top };
+            if start >= end {
+                break;
+            }
+            let free = free_bytes_in(heap, start, end);
+            map.cells[cell] = if free == end - start {
+                b'.'
+            }
+            else if free == 0 {
+                b'#'
+            }
+            else {
+                b'+'
+            };
+            map.cell_count += 1;
+        }
+        map
+    }
+}
+
+// Bytes in `[start, end)` that are covered by holes
+fn free_bytes_in(heap: &Heap, start: usize, end: usize) -> usize {
+    heap.holes.holes()
+        .take_while(|&(addr, _)| addr < end)
+        .map(|(addr, size)| {
+            let hole_start = if addr > start { addr } else { start };
+            let hole_end = if addr + size < end { addr + size } else { end };
+            if hole_end > hole_start { hole_end - hole_start } else { 0 }
+        })
+        .sum()
+}
+
+impl fmt::Display for FragmentationMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use core::fmt::Write;
+
+        if self.cell_size == 0 {
+            return write!(f, "heap is empty");
+        }
+
+        write!(f, "heap {:#x}..{:#x}, {:#x} bytes per cell", self.bottom, self.top, self.cell_size)?;
+        for (cell, &symbol) in self.cells[..self.cell_count].iter().enumerate() {
+            if cell % MAP_WIDTH == 0 {
+                write!(f, "\n{:#x} ", self.bottom + cell * self.cell_size)?;
+            }
+            f.write_char(symbol as char)?;
+        }
+        Ok(())
+    }
+}
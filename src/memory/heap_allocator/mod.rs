@@ -1 +1,13 @@
 pub mod linked_list_allocator;
+#[cfg(feature = "slab_allocator")]
+pub mod slab_allocator;
+
+use spin::Mutex;
+
+use interrupts::without_interrupts;
+
+// Runs `f` on a heap behind the global allocator. The lock is held with interrupts disabled, so a
+// thread can't be preempted while holding it and the scheduler can allocate.
+fn with_locked<H, R, F: FnOnce(&mut H) -> R>(heap: &Mutex<H>, f: F) -> R {
+    without_interrupts(|| f(&mut heap.lock()))
+}
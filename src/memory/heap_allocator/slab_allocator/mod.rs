@@ -0,0 +1,315 @@
+use core::fmt;
+use core::ptr::{self, Unique};
+use core::ops::Deref;
+use alloc::allocator::{Alloc, Layout, AllocErr};
+use spin::Mutex;
+
+use memory::PAGE_SIZE;
+use super::linked_list_allocator::{Heap, HeapGrowFn};
+use super::with_locked;
+
+const CLASS_COUNT: usize = 8;
+const SIZE_CLASSES: [usize; CLASS_COUNT] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+// Size of the chunks each size class takes from the backing heap at once
+const SLAB_SIZE: usize = PAGE_SIZE;
+
+// A free block of a size class. Like the holes of the linked list heap, the free blocks
+// themselves store the list.
+struct FreeBlock {
+    next: Option<Unique<FreeBlock>>,
+}
+
+// Free list and usage counters for all blocks of one size.
+struct SizeClass {
+    block_size: usize,
+    free_list: Option<Unique<FreeBlock>>,
+    capacity: usize,
+    in_use: usize,
+    allocations: usize,
+}
+
+impl SizeClass {
+    const fn new(block_size: usize) -> SizeClass {
+        SizeClass {
+            block_size,
+            free_list: None,
+            capacity: 0,
+            in_use: 0,
+            allocations: 0,
+        }
+    }
+
+    fn allocate(&mut self) -> Option<*mut u8> {
+        self.free_list.take().map(|block| {
+            let block = block.as_ptr();
+            self.free_list = unsafe { (*block).next.take() };
+            self.in_use += 1;
+            self.allocations += 1;
+            block as *mut u8
+        })
+    }
+
+    unsafe fn deallocate(&mut self, ptr: *mut u8) {
+        self.push(ptr);
+        self.in_use -= 1;
+    }
+
+    // Splits a slab taken from the backing heap into free blocks. The slab must be aligned to
+    // the block size.
+    unsafe fn add_slab(&mut self, slab: *mut u8) {
+        let count = SLAB_SIZE / self.block_size;
+        // Push in reverse so blocks are handed out in address order
+        for i in (0..count).rev() {
+            self.push(slab.offset((i * self.block_size) as isize));
+        }
+        self.capacity += count;
+    }
+
+    unsafe fn push(&mut self, ptr: *mut u8) {
+        let block = ptr as *mut FreeBlock;
+        ptr::write(block, FreeBlock {
+            next: self.free_list.take(),
+        });
+        self.free_list = Some(Unique::new_unchecked(block));
+    }
+}
+
+// A heap that serves small allocations from per-size free lists and everything else from a
+// linked list `Heap`. Slabs are taken from the backing heap as needed and never given back.
+pub struct SlabHeap {
+    classes: [SizeClass; CLASS_COUNT],
+    backing: Heap,
+}
+
+impl SlabHeap {
+    // Empty heap where all calls will fail until `init` is called.
+    pub const fn empty() -> SlabHeap {
+        SlabHeap {
+            classes: [
+                SizeClass::new(16),
+                SizeClass::new(32),
+                SizeClass::new(64),
+                SizeClass::new(128),
+                SizeClass::new(256),
+                SizeClass::new(512),
+                SizeClass::new(1024),
+                SizeClass::new(2048),
+            ],
+            backing: Heap::empty(),
+        }
+    }
+
+    // Initializes the backing heap. Same requirements as `Heap::init`.
+    pub unsafe fn init(&mut self, heap_bottom: usize, heap_size: usize) {
+        self.backing.init(heap_bottom, heap_size);
+    }
+
+    // Lets the backing heap grow, see `Heap::set_growth`.
+    pub fn set_growth(&mut self, max_size: usize, grow: HeapGrowFn) {
+        self.backing.set_growth(max_size, grow);
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let index = match Self::class_index(&layout) {
+            Some(index) => index,
+            None => return self.backing.allocate(layout),
+        };
+
+        if let Some(ptr) = self.classes[index].allocate() {
+            return Ok(ptr);
+        }
+
+        let slab_layout = Layout::from_size_align(SLAB_SIZE, self.classes[index].block_size).unwrap();
+        let slab = self.backing.allocate(slab_layout)
+            .map_err(|_| AllocErr::Exhausted { request: layout })?;
+        unsafe {
+            self.classes[index].add_slab(slab);
+        }
+        Ok(self.classes[index].allocate().unwrap())
+    }
+
+    // Frees the allocation given by `ptr` and `layout`. `ptr` must have been returned by
+    // `allocate` with an identical layout.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        match Self::class_index(&layout) {
+            Some(index) => self.classes[index].deallocate(ptr),
+            None => self.backing.deallocate(ptr, layout),
+        }
+    }
+
+    // The linked list heap that backs the slabs and the large allocations.
+    pub fn backing_heap(&self) -> &Heap {
+        &self.backing
+    }
+
+    pub fn statistics(&self) -> SlabStatistics {
+        let mut classes = [SizeClassStatistics::default(); CLASS_COUNT];
+        for (stats, class) in classes.iter_mut().zip(self.classes.iter()) {
+            *stats = SizeClassStatistics {
+                block_size: class.block_size,
+                in_use: class.in_use,
+                capacity: class.capacity,
+                allocations: class.allocations,
+            };
+        }
+        SlabStatistics {
+            classes,
+        }
+    }
+
+    // Returns the smallest size class that fits the size and alignment of `layout`, or `None`
+    // if the layout is too big for any of them.
+    fn class_index(layout: &Layout) -> Option<usize> {
+        // Blocks are aligned to their size, so the alignment only needs to fit in the block
+        let size = if layout.size() > layout.align() { layout.size() } else { layout.align() };
+        SIZE_CLASSES.iter().position(|&class_size| class_size >= size)
+    }
+}
+
+unsafe impl Alloc for SlabHeap {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        self.allocate(layout)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        self.deallocate(ptr, layout);
+    }
+}
+
+// Usage counters of a single size class.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SizeClassStatistics {
+    pub block_size: usize,
+    // Blocks that are currently allocated
+    pub in_use: usize,
+    // Blocks carved out of slabs so far, both free and allocated
+    pub capacity: usize,
+    // Total number of allocations served by this class
+    pub allocations: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStatistics {
+    pub classes: [SizeClassStatistics; CLASS_COUNT],
+}
+
+impl fmt::Display for SlabStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "class  in use  capacity  allocations")?;
+        for class in self.classes.iter() {
+            write!(f, "\n{:>5}  {:>6}  {:>8}  {:>11}",
+                class.block_size, class.in_use, class.capacity, class.allocations)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct LockedSlabHeap(Mutex<SlabHeap>);
+
+impl LockedSlabHeap {
+    // Create an empty heap
+    pub const fn empty() -> LockedSlabHeap {
+        LockedSlabHeap(Mutex::new(SlabHeap::empty()))
+    }
+}
+
+impl Deref for LockedSlabHeap {
+    type Target = Mutex<SlabHeap>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+unsafe impl<'a> Alloc for &'a LockedSlabHeap {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        with_locked(&self.0, |heap| heap.allocate(layout))
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        with_locked(&self.0, |heap| heap.deallocate(ptr, layout));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::size_of;
+    use alloc::allocator::Layout;
+
+    use super::{SlabHeap, SIZE_CLASSES, SLAB_SIZE};
+
+    const HEAP_SIZE: usize = 8 * SLAB_SIZE;
+
+    // The heap lives in the memory it manages, so the memory must outlive it
+    fn new_heap() -> (Vec<usize>, SlabHeap) {
+        let mut memory = vec![0usize; HEAP_SIZE / size_of::<usize>()];
+        let mut heap = SlabHeap::empty();
+        unsafe { heap.init(memory.as_mut_ptr() as usize, HEAP_SIZE) };
+        (memory, heap)
+    }
+
+    fn layout(size: usize, align: usize) -> Layout {
+        Layout::from_size_align(size, align).unwrap()
+    }
+
+    #[test]
+    fn smallest_fitting_class_is_chosen() {
+        assert_eq!(SlabHeap::class_index(&layout(1, 1)), Some(0));
+        assert_eq!(SlabHeap::class_index(&layout(16, 8)), Some(0));
+        assert_eq!(SlabHeap::class_index(&layout(17, 8)), Some(1));
+        assert_eq!(SlabHeap::class_index(&layout(2048, 8)), Some(7));
+        assert_eq!(SlabHeap::class_index(&layout(2049, 8)), None);
+    }
+
+    #[test]
+    fn alignment_picks_a_big_enough_class() {
+        assert_eq!(SlabHeap::class_index(&layout(8, 64)), Some(2));
+        assert_eq!(SlabHeap::class_index(&layout(8, 4096)), None);
+    }
+
+    #[test]
+    fn blocks_are_aligned_to_their_class() {
+        let (_memory, mut heap) = new_heap();
+        for &(size, align) in [(24, 8), (8, 64), (300, 16), (1, 2048)].iter() {
+            let ptr = heap.allocate(layout(size, align)).unwrap();
+            let index = SlabHeap::class_index(&layout(size, align)).unwrap();
+            assert_eq!(ptr as usize % SIZE_CLASSES[index], 0);
+        }
+    }
+
+    #[test]
+    fn blocks_are_handed_out_in_address_order() {
+        let (_memory, mut heap) = new_heap();
+        let a = heap.allocate(layout(32, 8)).unwrap();
+        let b = heap.allocate(layout(32, 8)).unwrap();
+        assert_eq!(b as usize, a as usize + 32);
+
+        let statistics = heap.statistics().classes[1];
+        assert_eq!((statistics.in_use, statistics.capacity), (2, SLAB_SIZE / 32));
+    }
+
+    #[test]
+    fn freed_blocks_are_reused() {
+        let (_memory, mut heap) = new_heap();
+        let a = heap.allocate(layout(64, 8)).unwrap();
+        heap.allocate(layout(64, 8)).unwrap();
+        unsafe { heap.deallocate(a, layout(64, 8)) };
+        assert_eq!(heap.statistics().classes[2].in_use, 1);
+
+        assert_eq!(heap.allocate(layout(64, 8)).unwrap(), a);
+        let statistics = heap.statistics().classes[2];
+        assert_eq!((statistics.in_use, statistics.allocations), (2, 3));
+        // No second slab was needed
+        assert_eq!(statistics.capacity, SLAB_SIZE / 64);
+    }
+
+    #[test]
+    fn large_allocations_bypass_the_classes() {
+        let (_memory, mut heap) = new_heap();
+        let ptr = heap.allocate(layout(SLAB_SIZE, 8)).unwrap();
+        assert!(heap.statistics().classes.iter().all(|class| class.capacity == 0));
+        assert_eq!(heap.backing_heap().statistics().live_allocations, 1);
+        unsafe { heap.deallocate(ptr, layout(SLAB_SIZE, 8)) };
+        assert_eq!(heap.backing_heap().statistics().live_allocations, 0);
+    }
+}
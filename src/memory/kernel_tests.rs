@@ -0,0 +1,66 @@
+use alloc::Vec;
+
+use core::ptr;
+
+use {HEAP_START, HEAP_SIZE, HEAP_MAX_SIZE};
+use super::{paging, with_controller, PAGE_SIZE};
+
+kernel_test! {
+    fn buddy_blocks_are_aligned_and_merge_back() {
+        with_controller(|controller| {
+            let free_frames = controller.frame_statistics().total_free_frames();
+
+            let frame = controller.alloc_frames(3).expect("out of frames");
+            assert_eq!(frame.start_address() % (PAGE_SIZE << 3), 0);
+            assert_eq!(controller.frame_statistics().total_free_frames(), free_frames - 8);
+
+            controller.dealloc_frames(frame, 3);
+            assert_eq!(controller.frame_statistics().total_free_frames(), free_frames);
+        });
+    }
+}
+
+kernel_test! {
+    fn heap_grows_past_its_initial_size() {
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.resize(2 * HEAP_SIZE, 0xab);
+        assert!(bytes.iter().all(|&byte| byte == 0xab));
+    }
+}
+
+kernel_test! {
+    fn heap_area_is_mapped_on_first_touch() {
+        // The heap never grows this far in the tests
+        let address = HEAP_START + HEAP_MAX_SIZE - PAGE_SIZE;
+        assert!(!paging::walk(address).is_mapped());
+
+        unsafe { ptr::write_volatile(address as *mut u8, 0xab) };
+        assert!(paging::walk(address).is_mapped());
+        assert_eq!(unsafe { ptr::read_volatile(address as *const u8) }, 0xab);
+    }
+}
+
+kernel_test! {
+    fn null_page_is_unmapped() {
+        assert!(!paging::walk(0).is_mapped());
+        assert!(paging::walk(HEAP_START).is_mapped());
+        assert!(paging::walk(::rust_main as usize).is_mapped());
+    }
+}
+
+kernel_test! {
+    fn freed_stacks_are_unmapped_and_reused() {
+        with_controller(|controller| {
+            let stack = controller.alloc_stack(2).expect("out of stack space");
+            let (bottom, top) = (stack.bottom(), stack.top());
+            assert!(paging::walk(bottom).is_mapped() && paging::walk(top - 1).is_mapped());
+
+            controller.dealloc_stack(stack);
+            assert!(!paging::walk(bottom).is_mapped());
+
+            let stack = controller.alloc_stack(2).expect("out of stack space");
+            assert_eq!((stack.bottom(), stack.top()), (bottom, top));
+            controller.dealloc_stack(stack);
+        });
+    }
+}
@@ -1,20 +1,35 @@
-pub mod area_frame_allocator;
+pub mod buddy_frame_allocator;
 pub mod heap_allocator;
+mod kernel_tests;
 pub mod paging;
 pub mod stack_allocator;
 
 use multiboot2::BootInformation;
+use spin::{Mutex, Once};
+use x86_64::structures::idt::{PageFaultErrorCode, PROTECTION_VIOLATION};
 
+use elf;
+use interrupts::{self, without_interrupts};
+
+use self::paging::PageIter;
 use self::paging::entry::EntryFlags;
-pub use self::area_frame_allocator::AreaFrameAllocator;
+pub use self::buddy_frame_allocator::{BuddyFrameAllocator, BuddyStatistics};
 
 pub const PAGE_SIZE: usize = 4096;
 
+// Stacks are allocated from this many pages right above the heap's growth limit
+const STACK_AREA_PAGES: usize = 101;
+// End of the heap and the stack area above it. Physical memory in `[HEAP_START, DATA_AREA_END)`
+// can't be identity mapped.
+const DATA_AREA_END: usize = ::HEAP_START + ::HEAP_MAX_SIZE + STACK_AREA_PAGES * PAGE_SIZE;
+
 pub use self::stack_allocator::Stack;
 
+static MEMORY_CONTROLLER: Once<Mutex<MemoryController>> = Once::new();
+
 pub struct MemoryController {
     active_table: paging::ActivePageTable,
-    frame_allocator: AreaFrameAllocator,
+    frame_allocator: BuddyFrameAllocator,
     stack_allocator: stack_allocator::StackAllocator,
 }
 
@@ -28,6 +43,164 @@ impl MemoryController {
 
         stack_allocator.alloc_stack(active_table, frame_allocator, size_in_pages)
     }
+
+    pub fn dealloc_stack(&mut self, stack: Stack) {
+        let &mut MemoryController {
+            ref mut active_table,
+            ref mut frame_allocator,
+            ref mut stack_allocator,
+        } = self;
+
+        stack_allocator.dealloc_stack(active_table, frame_allocator, stack)
+    }
+
+    // Allocates 2^order physically contiguous frames, e.g. for DMA buffers or huge pages
+    pub fn alloc_frames(&mut self, order: usize) -> Option<Frame> {
+        self.frame_allocator.allocate_frames(order)
+    }
+
+    pub fn dealloc_frames(&mut self, frame: Frame, order: usize) {
+        self.frame_allocator.deallocate_frames(frame, order)
+    }
+
+    pub fn frame_statistics(&self) -> BuddyStatistics {
+        self.frame_allocator.statistics()
+    }
+
+    // Identity maps the device memory at `[address, address + size)` uncached so drivers can
+    // access its registers. Pages that are already mapped are left alone.
+    pub fn identity_map_mmio(&mut self, address: usize, size: usize) {
+        self.identity_map_physical(address, size,
+            EntryFlags::WRITABLE | EntryFlags::NO_CACHE | EntryFlags::WRITE_THROUGH);
+    }
+
+    // Identity maps the physical memory at `[address, address + size)` with the given flags.
+    // Pages that are already identity mapped are left alone, the returned mapping records which
+    // ones. Panics if the range overlaps the heap or the stacks, or a page maps another frame.
+    pub fn identity_map_physical(&mut self, address: usize, size: usize, flags: EntryFlags) -> IdentityMapping {
+        use self::paging::Page;
+        use HEAP_START;
+
+        assert!(address + size <= HEAP_START || address >= DATA_AREA_END,
+            "{:#x}..{:#x} can't be identity mapped, the heap and stacks are there", address, address + size);
+
+        let &mut MemoryController {
+            ref mut active_table,
+            ref mut frame_allocator,
+            ..
+        } = self;
+
+        let frames = Frame::range_inclusive(
+            Frame::containing_address(address),
+            Frame::containing_address(address + size - 1));
+        let mut mapping = IdentityMapping {
+            first_page: Page::containing_address(address),
+            page_count: 0,
+            new_pages: 0,
+        };
+        for frame in frames {
+            match active_table.translate_page(Page::containing_address(frame.start_address())) {
+                Some(mapped) => assert!(mapped == frame,
+                    "page {:#x} is already mapped to frame {:#x}", frame.start_address(), mapped.start_address()),
+                None => {
+                    active_table.identity_map(frame, flags, frame_allocator);
+                    if mapping.page_count < MAX_TRACKED_PAGES {
+                        mapping.new_pages |= 1 << mapping.page_count;
+                    }
+                },
+            }
+            mapping.page_count += 1;
+        }
+        mapping
+    }
+
+    // Undoes `identity_map_physical` by unmapping the pages it mapped. Pages that were mapped
+    // before are kept. The frames are not freed since they belong to the firmware or a device.
+    pub fn identity_unmap_physical(&mut self, mapping: IdentityMapping) {
+        assert!(mapping.page_count <= MAX_TRACKED_PAGES,
+            "identity mappings of more than {} pages can not be removed", MAX_TRACKED_PAGES);
+
+        for index in 0..mapping.page_count {
+            if mapping.new_pages & (1 << index) != 0 {
+                self.active_table.unmap_page(mapping.first_page + index);
+            }
+        }
+    }
+
+    // Maps every page in `pages` to a newly allocated frame. If it runs out of frames, the pages
+    // mapped so far are unmapped again and `false` is returned.
+    pub fn try_map_range(&mut self, pages: PageIter, flags: EntryFlags) -> bool {
+        let &mut MemoryController {
+            ref mut active_table,
+            ref mut frame_allocator,
+            ..
+        } = self;
+
+        let mut mapped = pages.clone();
+        for page in pages {
+            match frame_allocator.allocate_frame() {
+                Some(frame) => active_table.map_to(page, frame, flags, frame_allocator),
+                None => {
+                    for mapped_page in mapped.take_while(|p| *p < page) {
+                        active_table.unmap(mapped_page, frame_allocator);
+                    }
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+// How many pages of an `IdentityMapping` are recorded, only mappings up to that size can be
+// removed again
+const MAX_TRACKED_PAGES: usize = 64;
+
+// The pages `MemoryController::identity_map_physical` mapped
+pub struct IdentityMapping {
+    first_page: paging::Page,
+    page_count: usize,
+    // Bit `n` is set if the `n`th page was not mapped before
+    new_pages: u64,
+}
+
+// Runs `f` with the memory controller once `init` has set it up. The lock is held with
+// interrupts disabled, so a thread holding it can't be preempted by one that spins on it.
+pub fn with_controller<R, F: FnOnce(&mut MemoryController) -> R>(f: F) -> R {
+    let controller = MEMORY_CONTROLLER.try().expect("memory::init has not been called yet");
+    without_interrupts(|| f(&mut controller.lock()))
+}
+
+// Like `with_controller`, but returns `None` instead of waiting if the controller is locked or
+// not set up yet. For code that may have interrupted its holder, e.g. the panic handler.
+pub fn try_with_controller<R, F: FnOnce(&mut MemoryController) -> R>(f: F) -> Option<R> {
+    let controller = MEMORY_CONTROLLER.try()?;
+    without_interrupts(|| controller.try_lock().map(|mut controller| f(&mut controller)))
+}
+
+// `HeapGrowFn` for the kernel heap. The pages above the initial heap are backed by
+// `map_on_demand` on first touch, so it only checks that `[top, top + size)` stays below
+// `HEAP_START + HEAP_MAX_SIZE`.
+pub fn grow_heap(top: usize, size: usize) -> bool {
+    use {HEAP_START, HEAP_MAX_SIZE};
+
+    top >= HEAP_START && top + size <= HEAP_START + HEAP_MAX_SIZE
+}
+
+// `PageFaultHook` that backs the faulting page with a new writable frame. Protection violations
+// are left alone, and so are faults while the memory controller is locked, which can't be
+// resolved without deadlocking.
+fn map_on_demand(address: usize, error_code: PageFaultErrorCode) -> bool {
+    use self::paging::Page;
+
+    if error_code.contains(PROTECTION_VIOLATION) {
+        return false;
+    }
+
+    let page = Page::containing_address(address);
+    try_with_controller(|controller| {
+        controller.try_map_range(Page::range_inclusive(page, page), EntryFlags::WRITABLE)
+    }).unwrap_or(false)
 }
 
 #[allow(dead_code)]
@@ -107,7 +280,7 @@ pub trait FrameAllocator {
     fn deallocate_frame(&mut self, frame: Frame);
 }
 
-pub fn init(boot_info: &BootInformation) -> MemoryController {
+pub fn init(boot_info: &BootInformation) {
     assert_has_not_been_called!("memory::init must only be called once!");
     let memory_map_tag = boot_info.memory_map_tag().expect("Memory Map Tag Required");
     let elf_sections_tag = boot_info.elf_sections_tag().expect("Elf Sections Tag Required!");
@@ -125,17 +298,27 @@ pub fn init(boot_info: &BootInformation) -> MemoryController {
     // kprintln!("Kernel start: {:#x}; Kernel end: {:#x}", kernel_start, kernel_end);
     // kprintln!("Multiboot start: {:#x}; Multiboot end: {:#x}", boot_info.start_address(), boot_info.end_address());
 
-    let mut frame_allocator = AreaFrameAllocator::new(
+    let mut frame_allocator = BuddyFrameAllocator::new(
         kernel_start as usize, kernel_end as usize,
         boot_info.start_address(), boot_info.end_address(),
         memory_map_tag.memory_areas());
+    // The boot loader puts the symbol table outside of the kernel image. Keep it for backtraces.
+    if let Some((symtab, strtab)) = elf::symbol_tables(elf_sections_tag) {
+        for table in [symtab, strtab].iter() {
+            frame_allocator.reserve_range(
+                Frame::containing_address(table.addr as usize),
+                Frame::containing_address((table.addr + table.size - 1) as usize));
+        }
+    }
     let mut active_table = self::paging::remap_the_kernel(&mut frame_allocator, boot_info);
 
     use self::paging::Page;
-    use {HEAP_START, HEAP_SIZE};
+    use {HEAP_START, HEAP_SIZE, HEAP_MAX_SIZE};
 
     let heap_start_page = Page::containing_address(HEAP_START);
     let heap_end_page = Page::containing_address(HEAP_START + HEAP_SIZE - 1);
+    // The heap can grow up to here, so nothing else may be placed in between
+    let heap_limit_page = Page::containing_address(HEAP_START + HEAP_MAX_SIZE - 1);
 
     active_table.map_range(
         Page::range_inclusive(heap_start_page, heap_end_page),
@@ -143,15 +326,108 @@ pub fn init(boot_info: &BootInformation) -> MemoryController {
     );
 
     let stack_allocator = {
-        let stack_alloc_start = heap_end_page + 1;
-        let stack_alloc_end = stack_alloc_start + 100;
+        let stack_alloc_start = heap_limit_page + 1;
+        let stack_alloc_end = stack_alloc_start + (STACK_AREA_PAGES - 1);
         let stack_alloc_range = Page::range_inclusive(stack_alloc_start, stack_alloc_end);
         stack_allocator::StackAllocator::new(stack_alloc_range)
     };
 
-    MemoryController {
+    // Only the initial heap is mapped up front, the rest of the heap area is mapped a page at a
+    // time when the grown heap first touches it
+    interrupts::register_page_fault_hook(HEAP_START + HEAP_SIZE, HEAP_START + HEAP_MAX_SIZE,
+                                         map_on_demand);
+
+    MEMORY_CONTROLLER.call_once(|| Mutex::new(MemoryController {
         active_table,
         frame_allocator,
         stack_allocator,
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::XorShift;
+
+    use super::{align_down, align_up, Frame, PAGE_SIZE};
+    use super::paging::Page;
+
+    #[test]
+    fn align_up_rounds_to_the_next_multiple() {
+        assert_eq!(align_up(0, 8), 0);
+        assert_eq!(align_up(1, 8), 8);
+        assert_eq!(align_up(8, 8), 8);
+        assert_eq!(align_up(PAGE_SIZE + 1, PAGE_SIZE), 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn align_down_rounds_to_the_previous_multiple() {
+        assert_eq!(align_down(0, 8), 0);
+        assert_eq!(align_down(7, 8), 0);
+        assert_eq!(align_down(8, 8), 8);
+        assert_eq!(align_down(2 * PAGE_SIZE - 1, PAGE_SIZE), PAGE_SIZE);
+        assert_eq!(align_down(13, 0), 13);
+    }
+
+    #[test]
+    #[should_panic]
+    fn align_rejects_other_alignments() {
+        align_down(13, 3);
+    }
+
+    #[test]
+    fn aligned_addresses_are_the_nearest_multiples() {
+        let mut rng = XorShift::new(0x1234_5678);
+        for _ in 0..10_000 {
+            let address = rng.below(1 << 40);
+            let align = 1 << rng.below(21);
+
+            let down = align_down(address, align);
+            assert_eq!(down % align, 0);
+            assert!(down <= address && address - down < align);
+
+            let up = align_up(address, align);
+            assert_eq!(up % align, 0);
+            assert!(up >= address && up - address < align);
+        }
+    }
+
+    #[test]
+    fn frames_contain_their_addresses() {
+        let frame = Frame::containing_address(PAGE_SIZE + 123);
+        assert_eq!(frame.start_address(), PAGE_SIZE);
+        assert_eq!(frame.end_address(), 2 * PAGE_SIZE - 1);
+        assert_eq!(Frame::containing_address(PAGE_SIZE - 1), Frame::containing_address(0));
+        assert!(Frame::containing_address(PAGE_SIZE) > Frame::containing_address(PAGE_SIZE - 1));
+    }
+
+    #[test]
+    fn frame_ranges_are_inclusive() {
+        let frames = Frame::range_inclusive(Frame::containing_address(0), Frame::containing_address(3 * PAGE_SIZE));
+        let addresses: Vec<usize> = frames.map(|frame| frame.start_address()).collect();
+        assert_eq!(addresses, [0, PAGE_SIZE, 2 * PAGE_SIZE, 3 * PAGE_SIZE]);
+
+        let empty = Frame::range_inclusive(Frame::containing_address(PAGE_SIZE), Frame::containing_address(0));
+        assert_eq!(empty.count(), 0);
+    }
+
+    #[test]
+    fn pages_contain_their_addresses() {
+        let page = Page::containing_address(0xffff_8000_0000_1234);
+        assert_eq!(page.start_address(), 0xffff_8000_0000_1000);
+        assert_eq!((page + 1).start_address(), 0xffff_8000_0000_2000);
+    }
+
+    #[test]
+    #[should_panic]
+    fn pages_reject_non_canonical_addresses() {
+        Page::containing_address(0x0000_8000_0000_0000);
+    }
+
+    #[test]
+    fn page_ranges_are_inclusive() {
+        let start = Page::containing_address(0x1000);
+        let pages = Page::range_inclusive(start, start + 2);
+        assert_eq!(pages.collect::<Vec<Page>>(), [start, start + 1, start + 2]);
+        assert_eq!(Page::range_inclusive(start + 1, start).count(), 0);
     }
 }
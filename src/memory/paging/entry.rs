@@ -2,6 +2,7 @@ use multiboot2::ElfSection;
 
 use memory::Frame;
 
+#[derive(Clone, Copy)]
 pub struct PageEntry(u64);
 
 impl PageEntry {
@@ -56,6 +56,12 @@ impl Mapper {
     }
 
     pub fn unmap<A: FrameAllocator>(&mut self, page: Page, allocator: &mut A) {
+        let frame = self.unmap_page(page);
+        allocator.deallocate_frame(frame);
+    }
+
+    // Unmaps the page without freeing its frame, e.g. because the frame is not ordinary RAM
+    pub fn unmap_page(&mut self, page: Page) -> Frame {
         assert!(self.translate(page.start_address()).is_some());
 
         let p1 = self.p4_mut()
@@ -71,7 +77,7 @@ impl Mapper {
         use x86_64::VirtualAddress;
         tlb::flush(VirtualAddress(page.start_address()));
 
-        allocator.deallocate_frame(frame);
+        frame
     }
 
     pub fn translate(&self, vaddr: VirtualAddress) -> Option<PhysicalAddress> {
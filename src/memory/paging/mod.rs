@@ -12,6 +12,9 @@ pub mod entry;
 mod mapper;
 pub mod table;
 mod temporary_page;
+mod walk;
+
+pub use self::walk::{walk, TableWalk};
 
 // Number of entries in a page table
 const PAGE_ENTRY_COUNT: usize = 512;
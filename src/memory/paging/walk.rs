@@ -0,0 +1,77 @@
+use core::fmt;
+
+use super::{Page, VirtualAddress};
+use super::entry::{PageEntry, EntryFlags};
+use super::table;
+
+const LEVEL_NAMES: [&str; 4] = ["P4", "P3", "P2", "P1"];
+
+// The entries the MMU looks at to translate an address, from the P4 entry down to the P1 entry
+pub struct TableWalk {
+    address: VirtualAddress,
+    // (index into the table, entry) for each level that was reached
+    entries: [Option<(usize, PageEntry)>; 4],
+}
+
+// Walks the active page table for `address` without modifying it. The walk stops at the first
+// entry that does not point to another table, i.e. missing tables and huge pages.
+pub fn walk(address: VirtualAddress) -> TableWalk {
+    let page = Page::containing_address(address);
+    let mut walk = TableWalk {
+        address,
+        entries: [None; 4],
+    };
+
+    let p4 = unsafe { &*table::P4 };
+    walk.entries[0] = Some((page.p4_index(), p4[page.p4_index()]));
+
+    let p3 = match p4.next_table(page.p4_index()) {
+        Some(p3) => p3,
+        None => return walk,
+    };
+    walk.entries[1] = Some((page.p3_index(), p3[page.p3_index()]));
+
+    let p2 = match p3.next_table(page.p3_index()) {
+        Some(p2) => p2,
+        None => return walk,
+    };
+    walk.entries[2] = Some((page.p2_index(), p2[page.p2_index()]));
+
+    if let Some(p1) = p2.next_table(page.p2_index()) {
+        walk.entries[3] = Some((page.p1_index(), p1[page.p1_index()]));
+    }
+    walk
+}
+
+impl TableWalk {
+    // Whether the walk ended at a present P1 entry or huge page, i.e. the address can be accessed
+    pub fn is_mapped(&self) -> bool {
+        let last = self.entries.iter().rposition(|entry| entry.is_some());
+        match last.and_then(|level| self.entries[level].map(|(_, entry)| (level, entry))) {
+            Some((level, entry)) => {
+                entry.flags().contains(EntryFlags::PRESENT) &&
+                    (level == 3 || entry.flags().contains(EntryFlags::HUGE_PAGE))
+            },
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for TableWalk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "page table walk for {:#x}:", self.address)?;
+        for (name, entry) in LEVEL_NAMES.iter().zip(self.entries.iter()) {
+            let (index, entry) = match *entry {
+                Some(entry) => entry,
+                None => break,
+            };
+            write!(f, "\n  {}[{:>3}] ", name, index)?;
+            match entry.pointed_frame() {
+                Some(frame) => write!(f, "-> {:#x} {:?}", frame.start_address(), entry.flags())?,
+                None if entry.is_unused() => write!(f, "unused")?,
+                None => write!(f, "not present {:?}", entry.flags() - EntryFlags::PRESENT)?,
+            }
+        }
+        Ok(())
+    }
+}
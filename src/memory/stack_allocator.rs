@@ -1,17 +1,24 @@
-
 use super::paging::{PageIter, Page, ActivePageTable};
 use super::paging::entry::EntryFlags;
 use super::{PAGE_SIZE, FrameAllocator};
 
+// How many freed stacks are kept for reuse. A stack freed while all of them are taken only gives
+// back its frames, its pages are lost.
+const MAX_FREE_STACKS: usize = 8;
+
 pub struct StackAllocator {
     range: PageIter,
+    // Unmapped stacks returned by `dealloc_stack`, which keep their guard pages. Not a `Vec`
+    // since the heap can't grow while the memory controller is locked.
+    free: [Option<Stack>; MAX_FREE_STACKS],
 }
 
 #[allow(dead_code)]
 impl StackAllocator {
     pub fn new(range: PageIter) -> StackAllocator {
         StackAllocator {
-            range
+            range,
+            free: [None, None, None, None, None, None, None, None],
         }
     }
 
@@ -23,6 +30,14 @@ impl StackAllocator {
             return None;
         }
 
+        let reusable = self.free.iter_mut()
+            .find(|slot| slot.as_ref().map_or(false, |stack| stack.size_in_pages() == size_in_pages));
+        if let Some(slot) = reusable {
+            let stack = slot.take().unwrap();
+            active_table.map_range(stack.pages(), EntryFlags::WRITABLE, frame_allocator);
+            return Some(stack);
+        }
+
         let mut range = self.range.clone();
 
         let guard_page = range.next();
@@ -49,6 +64,20 @@ impl StackAllocator {
             _ => None, // Not enough pages
         }
     }
+
+    // Unmaps the stack and frees its frames. Its pages are reused for the next stack of the same
+    // size. Never allocates.
+    pub fn dealloc_stack<FA: FrameAllocator>(&mut self,
+                           active_table: &mut ActivePageTable,
+                           frame_allocator: &mut FA,
+                           stack: Stack) {
+        for page in stack.pages() {
+            active_table.unmap(page, frame_allocator);
+        }
+        if let Some(slot) = self.free.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(stack);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -74,4 +103,12 @@ impl Stack {
     pub fn bottom(&self) -> usize {
         self.bottom
     }
+
+    pub fn size_in_pages(&self) -> usize {
+        (self.top - self.bottom) / PAGE_SIZE
+    }
+
+    fn pages(&self) -> PageIter {
+        Page::range_inclusive(Page::containing_address(self.bottom), Page::containing_address(self.top - 1))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,139 @@
+use core::ptr;
+use x86_64::instructions::{self, interrupts};
+use x86_64::instructions::tables::{self, DescriptorTablePointer};
+
+use acpi::{self, AddressSpace};
+use cmdline;
+use io::Port;
+
+// QEMU's `-device isa-debug-exit,iobase=0xf4,iosize=0x04`
+const QEMU_EXIT_PORT: u16 = 0xf4;
+
+const KEYBOARD_CONTROLLER_STATUS: u16 = 0x64;
+const KEYBOARD_CONTROLLER_COMMAND: u16 = 0x64;
+const KEYBOARD_CONTROLLER_INPUT_FULL: u8 = 1 << 1;
+// Pulses the CPU reset line
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xfe;
+
+// What to do after a panic or fatal exception has been printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicAction {
+    Halt,
+    Reboot,
+    // Exit QEMU through the isa-debug-exit device with this code
+    Exit(u32),
+}
+
+impl Default for PanicAction {
+    fn default() -> PanicAction {
+        PanicAction::Halt
+    }
+}
+
+impl PanicAction {
+    // Parses `halt`, `reboot`, `exit` or `exit:<code>`. `exit` alone exits with code 1.
+    pub fn parse(s: &str) -> Option<PanicAction> {
+        match s {
+            "halt" => Some(PanicAction::Halt),
+            "reboot" => Some(PanicAction::Reboot),
+            "exit" => Some(PanicAction::Exit(1)),
+            _ if s.starts_with("exit:") => s[5..].parse().ok().map(PanicAction::Exit),
+            _ => None,
+        }
+    }
+}
+
+// Halts until the next interrupt, forever. Everything left to do happens in interrupt handlers.
+// The scheduler's idle thread runs this.
+#[cfg(not(test))]
+pub fn idle() -> ! {
+    loop {
+        ::interrupts::enable_and_halt();
+    }
+}
+
+// Stops the CPU for good. Only an NMI can wake it up, after which it halts again.
+pub fn halt_forever() -> ! {
+    loop {
+        unsafe {
+            interrupts::disable();
+            instructions::halt();
+        }
+    }
+}
+
+// Resets the machine through the ACPI reset register, the keyboard controller or, if both
+// fail, a triple fault
+pub fn reboot() -> ! {
+    unsafe {
+        interrupts::disable();
+        acpi_reset();
+        keyboard_controller_reset();
+
+        // Without an IDT the next exception can't be delivered, which resets the CPU
+        tables::lidt(&DescriptorTablePointer {
+            limit: 0,
+            base: 0,
+        });
+        interrupts::int3();
+    }
+    halt_forever()
+}
+
+unsafe fn acpi_reset() {
+    let fadt = match acpi::tables().and_then(|tables| tables.fadt) {
+        Some(fadt) => fadt,
+        None => return,
+    };
+    let register = match fadt.reset_register {
+        Some(register) => register,
+        None => return,
+    };
+    match register.address_space {
+        AddressSpace::SystemIo => Port::<u8>::new(register.address as u16).write(fadt.reset_value),
+        AddressSpace::SystemMemory => {
+            // Don't wait for the memory controller, the panic might have happened while it was
+            // locked
+            let address = register.address as usize;
+            if ::memory::try_with_controller(|controller| controller.identity_map_mmio(address, 1)).is_some() {
+                ptr::write_volatile(address as *mut u8, fadt.reset_value);
+            }
+        },
+        // Resetting through PCI configuration space is not supported
+        _ => {},
+    }
+}
+
+unsafe fn keyboard_controller_reset() {
+    let mut status = Port::<u8>::new(KEYBOARD_CONTROLLER_STATUS);
+    let mut command = Port::<u8>::new(KEYBOARD_CONTROLLER_COMMAND);
+
+    // Machines without a controller read 0xff here, so don't wait forever
+    for _ in 0..0x10000 {
+        if status.read() & KEYBOARD_CONTROLLER_INPUT_FULL == 0 {
+            break;
+        }
+    }
+    command.write(KEYBOARD_CONTROLLER_RESET);
+}
+
+// Exits QEMU with status `(code << 1) | 1`. Halts if the isa-debug-exit device is missing.
+pub fn qemu_exit(code: u32) -> ! {
+    unsafe {
+        Port::<u32>::new(QEMU_EXIT_PORT).write(code);
+    }
+    halt_forever()
+}
+
+// Does what the `panic` option asks for once a panic has been printed. In test mode the tests
+// failed instead.
+pub fn after_panic() -> ! {
+    if cmdline::args().test {
+        ::ktest::fail();
+    }
+    match cmdline::args().panic {
+        PanicAction::Halt => halt_forever(),
+        PanicAction::Reboot => reboot(),
+        PanicAction::Exit(code) => qemu_exit(code),
+    }
+}
@@ -0,0 +1,51 @@
+use core::ptr;
+
+use memory::Stack;
+
+extern "C" {
+    // Defined in context_switch.asm
+    fn switch_context(old_stack_pointer: *mut usize, new_stack_pointer: usize);
+    fn thread_entry();
+}
+
+// Callee-saved registers `switch_context` pushes: rbx, rbp and r12 to r15
+const SAVED_REGISTERS: usize = 6;
+
+// What a thread that isn't running needs to continue. The registers are saved on its stack, so
+// that's only the stack pointer.
+pub struct Context {
+    stack_pointer: usize,
+}
+
+impl Context {
+    // For the running thread, the first `switch` away from it fills it in
+    pub const fn empty() -> Context {
+        Context {
+            stack_pointer: 0,
+        }
+    }
+
+    // A context that starts with `thread_entry` on the empty `stack`
+    pub fn new(stack: &Stack) -> Context {
+        // From the top: two zero words keeping the entry stack aligned and ending backtraces, the
+        // address `switch_context` returns to and the zeroed registers it pops
+        let top = stack.top() as *mut usize;
+        unsafe {
+            let return_address = top.offset(-3);
+            ptr::write(top.offset(-1), 0);
+            ptr::write(top.offset(-2), 0);
+            ptr::write(return_address, thread_entry as usize);
+            let registers = return_address.offset(-(SAVED_REGISTERS as isize));
+            ptr::write_bytes(registers, 0, SAVED_REGISTERS);
+            Context {
+                stack_pointer: registers as usize,
+            }
+        }
+    }
+}
+
+// Saves the running thread's context to `old` and continues with `new`. Returns when something
+// switches back to `old`. Interrupts must be disabled and `new` must not be running.
+pub unsafe fn switch(old: *mut Context, new: *const Context) {
+    switch_context(&mut (*old).stack_pointer, (*new).stack_pointer);
+}
@@ -0,0 +1,65 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use time::{Duration, Instant};
+use super::{current, sleep, spawn, yield_now, ThreadId};
+
+// Yields until `done` or panics after a second
+fn wait_for<F: Fn() -> bool>(done: F) {
+    let start = Instant::now();
+    while !done() {
+        assert!(start.elapsed() < Duration::from_secs(1), "timed out waiting for other threads");
+        yield_now();
+    }
+}
+
+kernel_test! {
+    fn spawned_threads_run_to_completion() {
+        static FINISHED: AtomicUsize = AtomicUsize::new(0);
+        fn worker() {
+            FINISHED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let ids = [spawn(worker), spawn(worker), spawn(worker)];
+        assert!(ids[0] != ids[1] && ids[1] != ids[2]);
+        wait_for(|| FINISHED.load(Ordering::SeqCst) == 3);
+    }
+}
+
+kernel_test! {
+    fn threads_have_their_own_ids() {
+        static WORKER_ID: AtomicUsize = AtomicUsize::new(0);
+        fn worker() {
+            let ThreadId(id) = current();
+            WORKER_ID.store(id, Ordering::SeqCst);
+        }
+
+        let ThreadId(id) = spawn(worker);
+        wait_for(|| WORKER_ID.load(Ordering::SeqCst) != 0);
+        assert_eq!(WORKER_ID.load(Ordering::SeqCst), id);
+        assert_eq!(current(), ThreadId(0));
+    }
+}
+
+kernel_test! {
+    fn sleep_blocks_for_at_least_the_duration() {
+        let start = Instant::now();
+        sleep(Duration::from_millis(20));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+}
+
+kernel_test! {
+    fn busy_threads_are_preempted() {
+        static RAN: AtomicBool = AtomicBool::new(false);
+        fn worker() {
+            RAN.store(true, Ordering::SeqCst);
+        }
+
+        spawn(worker);
+        // Never yields, only the timer can let the worker run
+        let start = Instant::now();
+        while !RAN.load(Ordering::SeqCst) {
+            assert!(start.elapsed() < Duration::from_secs(1), "the worker never ran");
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use alloc::boxed::Box;
+use alloc::{Vec, VecDeque};
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, Once};
+
+use x86_64::instructions::interrupts as cpu;
+
+use interrupts::{apic, without_interrupts};
+use memory::{self, Stack};
+use power;
+use time::{self, Duration};
+
+mod context;
+mod kernel_tests;
+
+use self::context::Context;
+
+// Stack size of spawned threads, plus a guard page
+const STACK_PAGES: usize = 4;
+// How long a thread runs before the timer switches to the next ready one
+const TIME_SLICE_MICROSECONDS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(usize);
+
+// The boot thread is 0
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+static SCHEDULER: Once<Mutex<Scheduler>> = Once::new();
+
+// Thread control block
+struct Thread {
+    id: ThreadId,
+    context: Context,
+    // `None` for the boot thread, which keeps running on the boot stack
+    stack: Option<Stack>,
+    // `None` for the boot thread, which was running before the scheduler
+    entry: Option<fn()>,
+    // `time::now` after which a sleeping thread is ready again
+    wake_at: u64,
+}
+
+impl Thread {
+    fn new(entry: fn(), stack: Stack) -> Thread {
+        Thread {
+            id: ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            context: Context::new(&stack),
+            stack: Some(stack),
+            entry: Some(entry),
+            wake_at: 0,
+        }
+    }
+}
+
+// What happens to the running thread when it's switched away from
+#[derive(Clone, Copy)]
+enum Previous {
+    Ready,
+    // Sleeps until `time::now` reaches this
+    Sleep(u64),
+    Exit,
+}
+
+// Round-robin scheduler. The threads are boxed, so their contexts stay put while they move
+// between the queues.
+struct Scheduler {
+    current: Box<Thread>,
+    ready: VecDeque<Box<Thread>>,
+    sleeping: Vec<Box<Thread>>,
+    // Exited threads whose stacks `reap` frees
+    dead: Vec<Box<Thread>>,
+    // Runs when no other thread is ready. `None` while it runs.
+    idle: Option<Box<Thread>>,
+    idle_id: ThreadId,
+}
+
+impl Scheduler {
+    fn wake_sleepers(&mut self) {
+        let now = time::now();
+        let mut index = 0;
+        while index < self.sleeping.len() {
+            if self.sleeping[index].wake_at <= now {
+                let thread = self.sleeping.swap_remove(index);
+                self.ready.push_back(thread);
+            }
+            else {
+                index += 1;
+            }
+        }
+    }
+
+    // Makes the next ready thread, or the idle thread if there is none, the current one and
+    // returns the contexts to switch between. Returns `None` if the current thread should keep
+    // running.
+    fn switch_to_next(&mut self, previous: Previous) -> Option<(*mut Context, *const Context)> {
+        let next = match self.ready.pop_front() {
+            Some(next) => next,
+            None => match previous {
+                Previous::Ready => return None,
+                _ => self.idle.take().expect("the idle thread can't block"),
+            },
+        };
+
+        let mut thread = mem::replace(&mut self.current, next);
+        let old_context: *mut Context = &mut thread.context;
+        match previous {
+            Previous::Ready if thread.id == self.idle_id => self.idle = Some(thread),
+            Previous::Ready => self.ready.push_back(thread),
+            Previous::Sleep(wake_at) => {
+                thread.wake_at = wake_at;
+                self.sleeping.push(thread);
+            },
+            Previous::Exit => self.dead.push(thread),
+        }
+        let new_context: *const Context = &self.current.context;
+        Some((old_context, new_context))
+    }
+}
+
+fn scheduler() -> Option<&'static Mutex<Scheduler>> {
+    SCHEDULER.try()
+}
+
+// Switches away from the current thread, which becomes `previous`
+fn schedule(previous: Previous) {
+    let scheduler = scheduler().expect("The scheduler has not been initialized!");
+    without_interrupts(|| {
+        // The next thread must find the scheduler unlocked
+        let contexts = scheduler.lock().switch_to_next(previous);
+        if let Some((old, new)) = contexts {
+            unsafe { context::switch(old, new) };
+        }
+    });
+}
+
+// Called from the APIC timer interrupt after the EOI, interrupts are disabled
+fn preempt() {
+    if let Some(scheduler) = scheduler() {
+        scheduler.lock().wake_sleepers();
+        schedule(Previous::Ready);
+    }
+}
+
+// Frees the stacks of exited threads. The exiting thread still runs on its stack, so another
+// thread has to do it.
+fn reap() {
+    let scheduler = match scheduler() {
+        Some(scheduler) => scheduler,
+        None => return,
+    };
+    let dead = without_interrupts(|| mem::replace(&mut scheduler.lock().dead, Vec::new()));
+    memory::with_controller(|controller| {
+        for thread in dead {
+            if let Some(stack) = thread.stack {
+                controller.dealloc_stack(stack);
+            }
+        }
+    });
+}
+
+fn idle_loop() {
+    power::idle()
+}
+
+// Every new thread starts here, see `Context::new`
+#[no_mangle]
+pub extern "C" fn thread_start() -> ! {
+    let entry = without_interrupts(|| {
+        let scheduler = scheduler().expect("The scheduler has not been initialized!");
+        scheduler.lock().current.entry
+    });
+    // `schedule` switched here with interrupts disabled
+    unsafe { cpu::enable() };
+    (entry.expect("the boot thread was started again"))();
+    exit()
+}
+
+// Starts a kernel thread running `entry`. It exits when `entry` returns.
+pub fn spawn(entry: fn()) -> ThreadId {
+    reap();
+    let stack = memory::with_controller(|controller| controller.alloc_stack(STACK_PAGES))
+        .expect("Could not allocate a thread stack");
+    let thread = Box::new(Thread::new(entry, stack));
+    let id = thread.id;
+    let scheduler = scheduler().expect("The scheduler has not been initialized!");
+    without_interrupts(|| scheduler.lock().ready.push_back(thread));
+    id
+}
+
+// Lets the other ready threads run first
+pub fn yield_now() {
+    if scheduler().is_some() {
+        schedule(Previous::Ready);
+    }
+}
+
+// Blocks the current thread for at least `duration`. Sleepers are woken up by the timer, so it
+// takes up to a time slice longer. Before `init` it busy waits.
+pub fn sleep(duration: Duration) {
+    if scheduler().is_none() {
+        return time::delay(duration);
+    }
+    schedule(Previous::Sleep(time::now() + duration.as_nanos()));
+}
+
+// Ends the current thread. Its stack is freed by the next `spawn`.
+pub fn exit() -> ! {
+    schedule(Previous::Exit);
+    unreachable!("an exited thread was scheduled");
+}
+
+pub fn current() -> ThreadId {
+    match scheduler() {
+        Some(scheduler) => without_interrupts(|| scheduler.lock().current.id),
+        None => ThreadId(0),
+    }
+}
+
+// Turns the running code into the boot thread and starts preempting it every time slice with
+// the local APIC timer, which must be calibrated already
+pub fn init() {
+    assert_has_not_been_called!("The scheduler must only be initialized once!");
+
+    let stack = memory::with_controller(|controller| controller.alloc_stack(STACK_PAGES))
+        .expect("Could not allocate the idle thread stack");
+    let idle = Box::new(Thread::new(idle_loop, stack));
+    let boot = Box::new(Thread {
+        id: ThreadId(0),
+        context: Context::empty(),
+        stack: None,
+        entry: None,
+        wake_at: 0,
+    });
+    SCHEDULER.call_once(|| Mutex::new(Scheduler {
+        current: boot,
+        ready: VecDeque::new(),
+        sleeping: Vec::new(),
+        dead: Vec::new(),
+        idle_id: idle.id,
+        idle: Some(idle),
+    }));
+
+    apic::set_timer_handler(preempt);
+    apic::local_apic().start_timer(apic::TimerMode::Periodic, apic::TIMER_VECTOR, TIME_SLICE_MICROSECONDS);
+}
@@ -0,0 +1,120 @@
+use core::{cmp, ptr};
+use spin::Mutex;
+
+use acpi::{self, AddressSpace};
+use interrupts::without_interrupts;
+use memory::{MemoryController, PAGE_SIZE};
+use super::{scale, ClockSource};
+
+// Where chipsets put the first HPET when the firmware doesn't describe it
+pub const DEFAULT_ADDRESS: usize = 0xfed0_0000;
+
+// The specification caps the counter period at 100ns
+const MAX_PERIOD_FEMTOSECONDS: u64 = 100_000_000;
+
+const CAPABILITIES: usize = 0x00;
+const CONFIGURATION: usize = 0x10;
+const MAIN_COUNTER: usize = 0xf0;
+
+const CAPABILITY_COUNTER_64_BIT: u64 = 1 << 13;
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+const CONFIGURATION_LEGACY_REPLACEMENT: u64 = 1 << 1;
+
+pub struct Hpet {
+    base: usize,
+    period_femtoseconds: u64,
+    counter_64_bit: bool,
+    // Last value of a 32 bit counter extended to 64 bits
+    extended_counter: Mutex<u64>,
+}
+
+impl Hpet {
+    fn read(&self, register: usize) -> u64 {
+        unsafe { ptr::read_volatile((self.base + register) as *const u64) }
+    }
+
+    fn write(&self, register: usize, val: u64) {
+        unsafe { ptr::write_volatile((self.base + register) as *mut u64, val) }
+    }
+
+    // Finds the HPET at `base`, which must be mapped. Returns `None` if the capabilities don't
+    // look like an HPET, e.g. because nothing is at that address.
+    unsafe fn probe(base: usize) -> Option<Hpet> {
+        let capabilities = ptr::read_volatile((base + CAPABILITIES) as *const u64);
+        let period_femtoseconds = capabilities >> 32;
+        if period_femtoseconds == 0 || period_femtoseconds > MAX_PERIOD_FEMTOSECONDS {
+            return None;
+        }
+        Some(Hpet {
+            base,
+            period_femtoseconds,
+            counter_64_bit: capabilities & CAPABILITY_COUNTER_64_BIT != 0,
+            extended_counter: Mutex::new(0),
+        })
+    }
+
+    // Restarts the main counter from zero. The comparators stay disabled, the PIT and RTC keep
+    // their interrupts.
+    fn start(&self) {
+        let configuration = self.read(CONFIGURATION) & !(CONFIGURATION_ENABLE | CONFIGURATION_LEGACY_REPLACEMENT);
+        self.write(CONFIGURATION, configuration);
+        self.write(MAIN_COUNTER, 0);
+        self.write(CONFIGURATION, configuration | CONFIGURATION_ENABLE);
+    }
+
+    pub fn frequency(&self) -> u64 {
+        1_000_000_000_000_000 / self.period_femtoseconds
+    }
+
+    // The main counter. A 32 bit counter is extended in software, which only works if it's read
+    // at least once per wrap around, i.e. every five minutes at the usual 14.3 MHz.
+    pub fn counter(&self) -> u64 {
+        if self.counter_64_bit {
+            return self.read(MAIN_COUNTER);
+        }
+        without_interrupts(|| {
+            let mut last = self.extended_counter.lock();
+            let low = self.read(MAIN_COUNTER) & 0xffff_ffff;
+            let mut counter = (*last & !0xffff_ffff) | low;
+            if counter < *last {
+                counter += 1 << 32;
+            }
+            *last = counter;
+            counter
+        })
+    }
+}
+
+impl ClockSource for Hpet {
+    fn name(&self) -> &'static str {
+        "hpet"
+    }
+
+    fn nanoseconds(&self) -> u64 {
+        scale(self.counter(), self.period_femtoseconds)
+    }
+
+    fn resolution(&self) -> u64 {
+        cmp::max(scale(1, self.period_femtoseconds), 1)
+    }
+}
+
+// Maps and starts the HPET the ACPI tables describe or the one at `DEFAULT_ADDRESS`
+pub fn init(memory_controller: &mut MemoryController) -> Option<Hpet> {
+    let address = match acpi::tables().and_then(|tables| tables.hpet) {
+        Some(ref hpet) if hpet.base_address.address_space == AddressSpace::SystemMemory =>
+            hpet.base_address.address as usize,
+        Some(_) => {
+            warn!("The HPET is not memory mapped");
+            return None;
+        },
+        None => DEFAULT_ADDRESS,
+    };
+
+    memory_controller.identity_map_mmio(address, PAGE_SIZE);
+    let hpet = unsafe { Hpet::probe(address)? };
+    hpet.start();
+    info!("HPET at {:#x}, {} Hz, {} bit counter", address, hpet.frequency(),
+        if hpet.counter_64_bit { 64 } else { 32 });
+    Some(hpet)
+}
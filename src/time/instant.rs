@@ -0,0 +1,190 @@
+use core::fmt;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+const NANOS_PER_MICRO: u64 = 1_000;
+const NANOS_PER_MILLI: u64 = 1_000_000;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// A span of time with nanosecond precision. Arithmetic saturates instead of overflowing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Duration(u64);
+
+impl Duration {
+    pub const fn from_nanos(nanoseconds: u64) -> Duration {
+        Duration(nanoseconds)
+    }
+
+    pub fn from_micros(microseconds: u64) -> Duration {
+        Duration(microseconds.saturating_mul(NANOS_PER_MICRO))
+    }
+
+    pub fn from_millis(milliseconds: u64) -> Duration {
+        Duration(milliseconds.saturating_mul(NANOS_PER_MILLI))
+    }
+
+    pub fn from_secs(seconds: u64) -> Duration {
+        Duration(seconds.saturating_mul(NANOS_PER_SEC))
+    }
+
+    pub fn as_nanos(&self) -> u64 {
+        self.0
+    }
+
+    pub fn as_micros(&self) -> u64 {
+        self.0 / NANOS_PER_MICRO
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.0 / NANOS_PER_MILLI
+    }
+
+    pub fn as_secs(&self) -> u64 {
+        self.0 / NANOS_PER_SEC
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_add(other.0))
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, other: Duration) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, other: Duration) -> Duration {
+        Duration(self.0.saturating_sub(other.0))
+    }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, other: Duration) {
+        *self = *self - other;
+    }
+}
+
+// Prints the largest unit that keeps the integer part non-zero, e.g. `1.500ms`
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (unit, scale) = if self.0 >= NANOS_PER_SEC {
+            ("s", NANOS_PER_SEC)
+        }
+        else if self.0 >= NANOS_PER_MILLI {
+            ("ms", NANOS_PER_MILLI)
+        }
+        else if self.0 >= NANOS_PER_MICRO {
+            ("us", NANOS_PER_MICRO)
+        }
+        else {
+            return write!(f, "{}ns", self.0);
+        };
+        // Three decimals, truncated
+        write!(f, "{}.{:03}{}", self.0 / scale, self.0 % scale / (scale / 1000), unit)
+    }
+}
+
+// A point in time of the clock `time::now` reads. Cheap to take with the TSC clock, so it's
+// suitable for profiling.
+//
+//     let start = Instant::now();
+//     ...
+//     debug!("took {}", start.elapsed());
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(super::now())
+    }
+
+    // Time since boot at this instant
+    pub fn since_boot(&self) -> Duration {
+        Duration(self.0)
+    }
+
+    // Zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_add(duration.0))
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        Instant(self.0.saturating_sub(duration.0))
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_convert_between_units() {
+        let duration = Duration::from_millis(1500);
+        assert_eq!(duration.as_nanos(), 1_500_000_000);
+        assert_eq!(duration.as_micros(), 1_500_000);
+        assert_eq!(duration.as_secs(), 1);
+        assert_eq!(Duration::from_secs(2), Duration::from_micros(2_000_000));
+    }
+
+    #[test]
+    fn duration_arithmetic_saturates() {
+        assert_eq!(Duration::from_secs(u64::max_value()), Duration::from_nanos(u64::max_value()));
+        assert_eq!(Duration::from_nanos(5) - Duration::from_nanos(7), Duration::from_nanos(0));
+        assert_eq!(Duration::from_nanos(u64::max_value()) + Duration::from_nanos(1),
+            Duration::from_nanos(u64::max_value()));
+    }
+
+    #[test]
+    fn durations_print_in_the_largest_unit() {
+        assert_eq!(format!("{}", Duration::from_nanos(999)), "999ns");
+        assert_eq!(format!("{}", Duration::from_nanos(1_500)), "1.500us");
+        assert_eq!(format!("{}", Duration::from_micros(2_345_678)), "2.345s");
+        assert_eq!(format!("{}", Duration::from_nanos(12_000_001)), "12.000ms");
+    }
+
+    #[test]
+    fn no_time_elapses_before_the_clock_is_set() {
+        assert_eq!(Instant::now().elapsed(), Duration::from_nanos(0));
+    }
+
+    #[test]
+    fn instants_order_by_time() {
+        let earlier = Instant(100);
+        let later = earlier + Duration::from_nanos(50);
+        assert!(later > earlier);
+        assert_eq!(later - earlier, Duration::from_nanos(50));
+        assert_eq!(earlier - later, Duration::from_nanos(0));
+        assert_eq!(later - Duration::from_nanos(50), earlier);
+    }
+}
@@ -0,0 +1,100 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{clock, delay, now, pit, rtc, sleep, tsc, Duration, Instant};
+use interrupts::{apic, without_interrupts};
+
+kernel_test! {
+    fn clock_never_goes_backwards() {
+        assert!(clock().is_some());
+        let mut previous = now();
+        for _ in 0..10_000 {
+            let current = now();
+            assert!(current >= previous);
+            previous = current;
+        }
+    }
+}
+
+kernel_test! {
+    fn delay_waits_long_enough() {
+        let start = now();
+        delay(Duration::from_millis(2));
+        assert!(now() - start >= 2_000_000 - clock().unwrap().resolution());
+    }
+}
+
+kernel_test! {
+    fn sleep_waits_long_enough() {
+        let start = now();
+        sleep(Duration::from_millis(5));
+        assert!(now() - start >= 5_000_000 - clock().unwrap().resolution());
+    }
+}
+
+kernel_test! {
+    fn pit_one_shot_runs_out() {
+        without_interrupts(|| {
+            pit::one_shot(500);
+            assert!(!pit::one_shot_expired());
+            pit::delay(600);
+            assert!(pit::one_shot_expired());
+            pit::resume_tick();
+        });
+    }
+}
+
+kernel_test! {
+    fn timers_are_calibrated() {
+        assert!(tsc::ticks_per_ms() > 0);
+        assert!(apic::local_apic().timer_ticks_per_ms() > 0);
+    }
+}
+
+kernel_test! {
+    fn instants_measure_elapsed_time() {
+        let start = Instant::now();
+        delay(Duration::from_micros(1000));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_micros(1000) - Duration::from_nanos(clock().unwrap().resolution()));
+        assert!(Instant::now() >= start + elapsed);
+    }
+}
+
+kernel_test! {
+    fn invariant_tsc_is_the_clock() {
+        if tsc::is_invariant() {
+            assert_eq!(clock().unwrap().name(), "tsc");
+        }
+    }
+}
+
+kernel_test! {
+    fn rtc_reads_a_plausible_date() {
+        let date_time = rtc::read();
+        assert!(date_time.year >= 2018);
+        assert!(date_time.month >= 1 && date_time.month <= 12);
+        assert!(date_time.day >= 1 && date_time.day <= 31);
+        assert!(date_time.hour < 24 && date_time.minute < 60 && date_time.second < 60);
+        // The clock and the RTC may disagree about when a second starts
+        assert!((rtc::unix_time().unwrap() as i64 - date_time.unix_timestamp()).abs() <= 1);
+    }
+}
+
+kernel_test! {
+    fn rtc_periodic_interrupt_fires() {
+        static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+        fn count() {
+            INTERRUPTS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        rtc::enable_periodic_interrupt(256, count);
+        sleep(Duration::from_millis(50));
+        rtc::disable_periodic_interrupt();
+        // About 12, leave room for a slow start
+        let interrupts = INTERRUPTS.load(Ordering::Relaxed);
+        assert!(interrupts >= 6);
+
+        sleep(Duration::from_millis(20));
+        assert_eq!(INTERRUPTS.load(Ordering::Relaxed), interrupts);
+    }
+}
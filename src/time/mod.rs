@@ -0,0 +1,145 @@
+use spin::Once;
+
+use x86_64::registers::flags::{self, IF};
+
+use interrupts::{self, apic, without_interrupts};
+use log;
+use memory::MemoryController;
+
+pub mod hpet;
+mod instant;
+mod kernel_tests;
+pub mod pit;
+pub mod rtc;
+pub mod tsc;
+
+pub use self::instant::{Duration, Instant};
+
+// Period of the PIT tick, which also wakes up `sleep`
+const TICK_MICROSECONDS: u64 = 1000;
+// How long counters are measured by `calibrate`
+const CALIBRATION_MS: u64 = 10;
+
+// A monotonic counter of nanoseconds
+pub trait ClockSource: Sync {
+    fn name(&self) -> &'static str;
+
+    // Nanoseconds since the source was started. Never goes backwards.
+    fn nanoseconds(&self) -> u64;
+
+    // How far `nanoseconds` advances at once
+    fn resolution(&self) -> u64;
+}
+
+static HPET: Once<hpet::Hpet> = Once::new();
+// The clock other clocks are calibrated against, the HPET or the PIT tick
+static REFERENCE: Once<&'static ClockSource> = Once::new();
+static CLOCK: Once<&'static ClockSource> = Once::new();
+
+// `count * femtoseconds_per_count` in nanoseconds without overflowing for any realistic count
+fn scale(count: u64, femtoseconds_per_count: u64) -> u64 {
+    const FEMTOSECONDS_PER_NANOSECOND: u64 = 1_000_000;
+    count / FEMTOSECONDS_PER_NANOSECOND * femtoseconds_per_count
+        + count % FEMTOSECONDS_PER_NANOSECOND * femtoseconds_per_count / FEMTOSECONDS_PER_NANOSECOND
+}
+
+// The clock `now` reads, `None` before `init`
+pub fn clock() -> Option<&'static ClockSource> {
+    CLOCK.try().map(|clock| *clock)
+}
+
+// Nanoseconds since the clock was started during boot, zero before that
+pub fn now() -> u64 {
+    clock().map_or(0, |clock| clock.nanoseconds())
+}
+
+// A clock that can time microseconds, the PIT tick can't
+fn precise(clock: Option<&'static ClockSource>) -> Option<&'static ClockSource> {
+    clock.and_then(|clock| if clock.resolution() <= 1000 { Some(clock) } else { None })
+}
+
+// Busy waits for at least `duration`
+pub fn delay(duration: Duration) {
+    match precise(clock()) {
+        Some(clock) => {
+            let deadline = clock.nanoseconds() + duration.as_nanos();
+            while clock.nanoseconds() < deadline {}
+        },
+        None => pit::delay(duration.as_micros()),
+    }
+}
+
+// Halts for at least `duration`. The PIT tick wakes the CPU up to check the time. With
+// interrupts disabled nothing would, so it busy waits instead.
+pub fn sleep(duration: Duration) {
+    if clock().is_none() || !flags::flags().contains(IF) {
+        return delay(duration);
+    }
+    let deadline = now() + duration.as_nanos();
+    while now() < deadline {
+        interrupts::enable_and_halt();
+    }
+}
+
+// How far the counter `read` advances per millisecond. It's measured with the HPET if there is
+// one, otherwise with PIT channel 2.
+pub fn calibrate<F: FnMut() -> u64>(mut read: F) -> u64 {
+    let clock = match precise(REFERENCE.try().map(|clock| *clock)) {
+        Some(clock) => clock,
+        None => return pit::measure(CALIBRATION_MS, read),
+    };
+    without_interrupts(|| {
+        let start_time = clock.nanoseconds();
+        let start = read();
+        let deadline = start_time + CALIBRATION_MS * 1_000_000;
+        let mut end_time = start_time;
+        while end_time < deadline {
+            end_time = clock.nanoseconds();
+        }
+        (read() - start) * 1_000_000 / (end_time - start_time)
+    })
+}
+
+// Starts the PIT tick and the HPET and calibrates the local APIC timer and the TSC against the
+// more precise of the two. The TSC becomes the clock if it's invariant, otherwise that reference
+// clock does. Finally reads the wall clock time from the RTC. Needs the I/O APICs for the tick.
+pub fn init(memory_controller: &mut MemoryController) {
+    assert_has_not_been_called!("time::init must only be called once!");
+
+    pit::start_tick(TICK_MICROSECONDS);
+    let reference: &'static ClockSource = match hpet::init(memory_controller) {
+        Some(hpet) => HPET.call_once(|| hpet),
+        None => &pit::CLOCK,
+    };
+    REFERENCE.call_once(|| reference);
+
+    let local_apic = apic::local_apic();
+    local_apic.calibrate_timer();
+    let tsc_source = tsc::init();
+    info!("TSC runs at {} kHz ({:?}{}), APIC timer at {} kHz", tsc::ticks_per_ms(), tsc_source,
+        if tsc::is_invariant() { ", invariant" } else { "" }, local_apic.timer_ticks_per_ms());
+
+    let clock: &'static ClockSource = match tsc::clock(reference.nanoseconds()) {
+        Some(tsc) => tsc,
+        None => reference,
+    };
+    CLOCK.call_once(|| clock);
+    log::set_clock(now);
+    info!("Clock source {}, resolution {}ns", clock.name(), clock.resolution());
+    rtc::init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::scale;
+
+    #[test]
+    fn scale_converts_femtoseconds() {
+        assert_eq!(scale(0, 69_841_279), 0);
+        assert_eq!(scale(1, 1_000_000), 1);
+        assert_eq!(scale(3, 838_095_110), 2514);
+        // A 14.3 MHz HPET after a year does not overflow
+        let count = 14_318_180 * 60 * 60 * 24 * 365;
+        assert_eq!(scale(count, 69_841_279) / 1_000_000_000, 60 * 60 * 24 * 365);
+    }
+}
@@ -0,0 +1,157 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use interrupts::{ioapic, irq, without_interrupts};
+use io::Port;
+use super::{scale, ClockSource};
+
+// The PIT counts down at this rate on every PC
+pub const FREQUENCY: u64 = 1_193_182;
+// Femtoseconds per PIT count, rounded
+const FEMTOSECONDS_PER_COUNT: u64 = 838_095_110;
+
+const CHANNEL0_PORT: u16 = 0x40;
+const CHANNEL2_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+// Gates channel 2 and reads back its output, shared with the PC speaker
+const GATE_PORT: u16 = 0x61;
+
+const SELECT_CHANNEL0: u8 = 0b00 << 6;
+const SELECT_CHANNEL2: u8 = 0b10 << 6;
+const ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+// Interrupt on terminal count, i.e. one-shot
+const MODE_ONE_SHOT: u8 = 0b000 << 1;
+const MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+// Latches the status byte of channel 0 but not its count
+const READ_BACK_CHANNEL0_STATUS: u8 = 0b11 << 6 | 1 << 5 | 1 << 1;
+// Output pin state in the status byte
+const STATUS_OUTPUT_HIGH: u8 = 1 << 7;
+
+const GATE_ENABLE: u8 = 1 << 0;
+const SPEAKER_ENABLE: u8 = 1 << 1;
+const OUTPUT_HIGH: u8 = 1 << 5;
+
+// Longest one-shot or period, a reload value of 0 counts 65536
+const MAX_COUNT: u64 = 0x1_0000;
+
+const VECTOR: u8 = irq::FIRST_VECTOR + ioapic::IRQ_PIT;
+
+// Channel 0 interrupts since `start_tick`
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+// The channel 0 reload value, zero while it's stopped
+static TICK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+// Ticks counted by channel 0 in periodic mode. Only advances with interrupts enabled.
+pub struct PitClock;
+
+pub static CLOCK: PitClock = PitClock;
+
+impl ClockSource for PitClock {
+    fn name(&self) -> &'static str {
+        "pit"
+    }
+
+    fn nanoseconds(&self) -> u64 {
+        let count = TICK_COUNT.load(Ordering::Relaxed) as u64;
+        scale(TICKS.load(Ordering::Relaxed) as u64 * count, FEMTOSECONDS_PER_COUNT)
+    }
+
+    fn resolution(&self) -> u64 {
+        scale(TICK_COUNT.load(Ordering::Relaxed) as u64, FEMTOSECONDS_PER_COUNT)
+    }
+}
+
+fn counts(microseconds: u64) -> u64 {
+    let counts = microseconds * FREQUENCY / 1_000_000;
+    assert!(counts > 0 && counts <= MAX_COUNT, "can not program the PIT for {}us", microseconds);
+    counts
+}
+
+unsafe fn program(channel_port: u16, command: u8, count: u64) {
+    Port::<u8>::new(COMMAND_PORT).write(command);
+    let mut channel = Port::<u8>::new(channel_port);
+    channel.write(count as u8);
+    channel.write((count >> 8) as u8);
+}
+
+fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+// Interrupts every `microseconds` on channel 0 and counts the ticks for `PitClock`. Only one
+// call is allowed, the tick count would not be monotonic across a rate change.
+pub fn start_tick(microseconds: u64) {
+    assert_has_not_been_called!("The PIT tick must only be started once!");
+    let count = counts(microseconds);
+    TICK_COUNT.store(count as usize, Ordering::Relaxed);
+    without_interrupts(|| unsafe {
+        program(CHANNEL0_PORT, SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_RATE_GENERATOR, count);
+    });
+    ioapic::route_irq(ioapic::IRQ_PIT, VECTOR, tick);
+}
+
+// Raises IRQ 0 once after `microseconds` instead of the next ticks, until `resume_tick`. The tick
+// handler counts it like any tick, so `PitClock` may be off by a tick afterwards.
+pub fn one_shot(microseconds: u64) {
+    let count = counts(microseconds);
+    without_interrupts(|| unsafe {
+        program(CHANNEL0_PORT, SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_ONE_SHOT, count);
+    });
+}
+
+// Restarts the tick `start_tick` started after a `one_shot`
+pub fn resume_tick() {
+    let count = TICK_COUNT.load(Ordering::Relaxed) as u64;
+    assert!(count > 0, "the PIT tick was never started");
+    without_interrupts(|| unsafe {
+        program(CHANNEL0_PORT, SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_RATE_GENERATOR, count);
+    });
+}
+
+// Whether channel 0 raised its output, i.e. a `one_shot` has run out
+pub fn one_shot_expired() -> bool {
+    without_interrupts(|| unsafe {
+        Port::<u8>::new(COMMAND_PORT).write(READ_BACK_CHANNEL0_STATUS);
+        Port::<u8>::new(CHANNEL0_PORT).read() & STATUS_OUTPUT_HIGH != 0
+    })
+}
+
+// Runs channel 2 for `counts` PIT counts and calls `f` right after starting it. Returns once it
+// has run out. Channel 2 isn't wired to an interrupt, so it's polled through the gate port.
+unsafe fn run_channel2<F: FnOnce()>(counts: u64, f: F) {
+    let mut gate = Port::<u8>::new(GATE_PORT);
+    // Keep the speaker quiet and stop channel 2 while programming it
+    let gate_value = gate.read() & !(SPEAKER_ENABLE | GATE_ENABLE);
+    gate.write(gate_value);
+
+    program(CHANNEL2_PORT, SELECT_CHANNEL2 | ACCESS_LOW_HIGH | MODE_ONE_SHOT, counts);
+    gate.write(gate_value | GATE_ENABLE);
+    f();
+
+    while gate.read() & OUTPUT_HIGH == 0 {}
+    gate.write(gate_value);
+}
+
+// Busy waits for `microseconds` with channel 2. Accurate to a few microseconds since every port
+// access takes about one.
+pub fn delay(microseconds: u64) {
+    let mut remaining = microseconds * FREQUENCY / 1_000_000;
+    while remaining > 0 {
+        let counts = if remaining > MAX_COUNT - 1 { MAX_COUNT - 1 } else { remaining };
+        without_interrupts(|| unsafe { run_channel2(counts, || {}) });
+        remaining -= counts;
+    }
+}
+
+// How much the counter `read` advances per millisecond, measured with channel 2 over
+// `milliseconds`
+pub fn measure<F: FnMut() -> u64>(milliseconds: u64, mut read: F) -> u64 {
+    let counts = FREQUENCY * milliseconds / 1000;
+    assert!(counts > 0 && counts < MAX_COUNT, "can not measure {}ms with the PIT", milliseconds);
+
+    let elapsed = without_interrupts(|| unsafe {
+        let mut start = 0;
+        run_channel2(counts, || start = read());
+        read() - start
+    });
+    elapsed * FREQUENCY / 1000 / counts
+}
@@ -0,0 +1,294 @@
+use core::{fmt, mem};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::{Mutex, Once};
+
+use acpi;
+use interrupts::{ioapic, irq, without_interrupts};
+use io::Port;
+
+const CMOS_INDEX_PORT: u16 = 0x70;
+const CMOS_DATA_PORT: u16 = 0x71;
+// Set in the index while the CMOS is accessed, so an NMI can't interrupt the access
+const NMI_DISABLE: u8 = 1 << 7;
+// Read-only, so the index is left here with NMIs enabled again after every access
+const REGISTER_STATUS_D: u8 = 0x0d;
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0a;
+const REGISTER_STATUS_B: u8 = 0x0b;
+const REGISTER_STATUS_C: u8 = 0x0c;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_A_RATE_MASK: u8 = 0x0f;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+const STATUS_B_PERIODIC_INTERRUPT: u8 = 1 << 6;
+const HOUR_PM: u8 = 1 << 7;
+
+// The periodic interrupt divides this
+const BASE_FREQUENCY: u32 = 32768;
+
+const VECTOR: u8 = irq::FIRST_VECTOR + ioapic::IRQ_RTC;
+
+// Serializes the index and data port accesses
+static CMOS: Mutex<()> = Mutex::new(());
+// Seconds since the UNIX epoch read at boot and `time::now` at that point
+static BOOT_TIME: Once<(u64, u64)> = Once::new();
+// Address of the periodic interrupt handler, 0 while it's disabled
+static PERIODIC_HANDLER: AtomicUsize = AtomicUsize::new(0);
+
+// Date and time in UTC, assuming the RTC keeps UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    // Seconds since 1970-01-01 00:00:00, negative before that
+    pub fn unix_timestamp(&self) -> i64 {
+        days_from_civil(self.year as i64, self.month as i64, self.day as i64) * 86400
+            + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+// Days between 1970-01-01 and the given date in the proleptic Gregorian calendar. Counts in
+// 400 year eras starting in March, so the leap day is the last day of the year.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+// Converts a raw hour register to 0-23
+fn decode_hour(raw: u8, binary: bool, is_24_hour: bool) -> u8 {
+    let pm = !is_24_hour && raw & HOUR_PM != 0;
+    let raw = raw & !HOUR_PM;
+    let hour = if binary { raw } else { from_bcd(raw) };
+    match (is_24_hour, pm, hour) {
+        (true, _, hour) => hour,
+        (false, false, 12) => 0,
+        (false, true, 12) => 12,
+        (false, false, hour) => hour,
+        (false, true, hour) => hour + 12,
+    }
+}
+
+// Selecting a register also sets the NMI mask, which is bit 7 of the index port
+unsafe fn enable_nmi() {
+    Port::<u8>::new(CMOS_INDEX_PORT).write(REGISTER_STATUS_D);
+}
+
+unsafe fn read_register(register: u8) -> u8 {
+    Port::<u8>::new(CMOS_INDEX_PORT).write(register | NMI_DISABLE);
+    let value = Port::<u8>::new(CMOS_DATA_PORT).read();
+    enable_nmi();
+    value
+}
+
+unsafe fn write_register(register: u8, val: u8) {
+    Port::<u8>::new(CMOS_INDEX_PORT).write(register | NMI_DISABLE);
+    Port::<u8>::new(CMOS_DATA_PORT).write(val);
+    enable_nmi();
+}
+
+// The time registers as stored, plus the century if there is a century register
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawTime([u8; 6], Option<u8>);
+
+unsafe fn read_raw(century_register: u8) -> RawTime {
+    while read_register(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {}
+    let registers = [REGISTER_SECONDS, REGISTER_MINUTES, REGISTER_HOURS, REGISTER_DAY,
+        REGISTER_MONTH, REGISTER_YEAR];
+    let mut values = [0; 6];
+    for (value, &register) in values.iter_mut().zip(registers.iter()) {
+        *value = read_register(register);
+    }
+    let century = if century_register != 0 { Some(read_register(century_register)) } else { None };
+    RawTime(values, century)
+}
+
+// Reads the current date and time. Without a century register in the FADT the year is assumed
+// to be in 2000-2099.
+pub fn read() -> DateTime {
+    let century_register = acpi::tables()
+        .and_then(|tables| tables.fadt)
+        .map_or(0, |fadt| fadt.century_register);
+
+    let (raw, status_b) = without_interrupts(|| {
+        let _cmos = CMOS.lock();
+        unsafe {
+            // An update can still start right after the flag was checked, so read until two
+            // reads agree
+            let mut raw = read_raw(century_register);
+            loop {
+                let again = read_raw(century_register);
+                if again == raw {
+                    break;
+                }
+                raw = again;
+            }
+            (raw, read_register(REGISTER_STATUS_B))
+        }
+    });
+
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let decode = |value: u8| if binary { value } else { from_bcd(value) };
+    let RawTime(values, century) = raw;
+    let century = century.map_or(20, |century| decode(century)) as u16;
+    DateTime {
+        year: century * 100 + decode(values[5]) as u16,
+        month: decode(values[4]),
+        day: decode(values[3]),
+        hour: decode_hour(values[2], binary, status_b & STATUS_B_24_HOUR != 0),
+        minute: decode(values[1]),
+        second: decode(values[0]),
+    }
+}
+
+// Seconds since the UNIX epoch, `None` before `init`. Counts with `time::now` from the time
+// read at boot.
+pub fn unix_time() -> Option<u64> {
+    BOOT_TIME.try().map(|&(boot_seconds, boot_nanoseconds)| {
+        boot_seconds + (super::now() - boot_nanoseconds) / 1_000_000_000
+    })
+}
+
+// Reads the wall clock time once, later calls to `unix_time` count from it
+pub fn init() {
+    let date_time = read();
+    let timestamp = date_time.unix_timestamp();
+    if timestamp < 0 {
+        warn!("The RTC is set to {}, before 1970", date_time);
+        return;
+    }
+    BOOT_TIME.call_once(|| (timestamp as u64, super::now()));
+    info!("RTC time {} UTC", date_time);
+}
+
+fn periodic_interrupt() {
+    // The RTC raises no further interrupts until register C was read
+    without_interrupts(|| {
+        let _cmos = CMOS.lock();
+        unsafe { read_register(REGISTER_STATUS_C) };
+    });
+    let handler = PERIODIC_HANDLER.load(Ordering::SeqCst);
+    if handler != 0 {
+        let handler: fn() = unsafe { mem::transmute(handler) };
+        handler();
+    }
+}
+
+// Calls `handler` at `frequency` Hz, which must be a power of two from 2 to 8192. Only one
+// handler can be installed until `disable_periodic_interrupt`.
+pub fn enable_periodic_interrupt(frequency: u32, handler: fn()) {
+    assert!(frequency.is_power_of_two() && frequency >= 2 && frequency <= 8192,
+        "the RTC can't interrupt at {} Hz", frequency);
+    // frequency = BASE_FREQUENCY >> (rate - 1)
+    let rate = (BASE_FREQUENCY / frequency).trailing_zeros() as u8 + 1;
+
+    let previous = PERIODIC_HANDLER.compare_and_swap(0, handler as usize, Ordering::SeqCst);
+    assert!(previous == 0, "the RTC periodic interrupt is already enabled");
+    ioapic::route_irq(ioapic::IRQ_RTC, VECTOR, periodic_interrupt);
+    without_interrupts(|| {
+        let _cmos = CMOS.lock();
+        unsafe {
+            let status_a = read_register(REGISTER_STATUS_A);
+            write_register(REGISTER_STATUS_A, (status_a & !STATUS_A_RATE_MASK) | rate);
+            let status_b = read_register(REGISTER_STATUS_B);
+            write_register(REGISTER_STATUS_B, status_b | STATUS_B_PERIODIC_INTERRUPT);
+            // Clear an interrupt that may already be pending
+            read_register(REGISTER_STATUS_C);
+        }
+    });
+}
+
+// Stops the periodic interrupt and removes its handler
+pub fn disable_periodic_interrupt() {
+    assert!(PERIODIC_HANDLER.load(Ordering::SeqCst) != 0, "the RTC periodic interrupt is not enabled");
+
+    without_interrupts(|| {
+        let _cmos = CMOS.lock();
+        unsafe {
+            let status_b = read_register(REGISTER_STATUS_B);
+            write_register(REGISTER_STATUS_B, status_b & !STATUS_B_PERIODIC_INTERRUPT);
+            read_register(REGISTER_STATUS_C);
+        }
+    });
+    ioapic::mask_irq(ioapic::IRQ_RTC);
+    irq::unregister_handler(VECTOR);
+    PERIODIC_HANDLER.store(0, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime { year, month, day, hour, minute, second }
+    }
+
+    #[test]
+    fn unix_timestamps_count_from_1970() {
+        assert_eq!(date_time(1970, 1, 1, 0, 0, 0).unix_timestamp(), 0);
+        assert_eq!(date_time(1969, 12, 31, 23, 59, 59).unix_timestamp(), -1);
+        assert_eq!(date_time(2000, 3, 1, 0, 0, 0).unix_timestamp(), 951_868_800);
+        assert_eq!(date_time(2018, 2, 14, 12, 30, 15).unix_timestamp(), 1_518_611_415);
+        assert_eq!(date_time(2038, 1, 19, 3, 14, 8).unix_timestamp(), 1 << 31);
+    }
+
+    #[test]
+    fn leap_days_are_counted() {
+        let feb_28 = date_time(2016, 2, 28, 0, 0, 0).unix_timestamp();
+        assert_eq!(date_time(2016, 3, 1, 0, 0, 0).unix_timestamp() - feb_28, 2 * 86400);
+        let feb_28 = date_time(2100, 2, 28, 0, 0, 0).unix_timestamp();
+        assert_eq!(date_time(2100, 3, 1, 0, 0, 0).unix_timestamp() - feb_28, 86400);
+    }
+
+    #[test]
+    fn there_is_no_wall_clock_time_before_init() {
+        assert_eq!(unix_time(), None);
+    }
+
+    #[test]
+    fn bcd_is_decoded() {
+        assert_eq!(from_bcd(0x00), 0);
+        assert_eq!(from_bcd(0x59), 59);
+        assert_eq!(from_bcd(0x99), 99);
+    }
+
+    #[test]
+    fn hours_are_decoded_in_all_modes() {
+        assert_eq!(decode_hour(0x23, false, true), 23);
+        assert_eq!(decode_hour(23, true, true), 23);
+        // 12 AM is midnight and 12 PM is noon
+        assert_eq!(decode_hour(0x12, false, false), 0);
+        assert_eq!(decode_hour(0x12 | HOUR_PM, false, false), 12);
+        assert_eq!(decode_hour(0x11 | HOUR_PM, false, false), 23);
+        assert_eq!(decode_hour(7 | HOUR_PM, true, false), 19);
+        assert_eq!(decode_hour(7, true, false), 7);
+    }
+}
@@ -0,0 +1,113 @@
+use core::cmp;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Once;
+
+use raw_cpuid::{self, CpuId};
+use x86_64::instructions::rdtsc;
+
+use super::{scale, ClockSource};
+
+const CPUID_TSC_INFO: u32 = 0x15;
+// How far in percent the TSC frequency may be from the processor base frequency without a warning
+const BASE_FREQUENCY_TOLERANCE: u64 = 5;
+
+static TICKS_PER_MS: AtomicUsize = AtomicUsize::new(0);
+static CLOCK: Once<TscClock> = Once::new();
+
+// Where the TSC frequency came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrequencySource {
+    // The crystal clock frequency and TSC ratio in CPUID leaf 0x15
+    CrystalClock,
+    // Measured against the reference clock
+    Calibrated,
+}
+
+// Counts TSC ticks since the clock was started
+pub struct TscClock {
+    start_ticks: u64,
+    // Nanoseconds of the clock the TSC took over from, to keep time monotonic
+    start_nanoseconds: u64,
+    femtoseconds_per_tick: u64,
+}
+
+impl ClockSource for TscClock {
+    fn name(&self) -> &'static str {
+        "tsc"
+    }
+
+    fn nanoseconds(&self) -> u64 {
+        self.start_nanoseconds + scale(rdtsc() - self.start_ticks, self.femtoseconds_per_tick)
+    }
+
+    fn resolution(&self) -> u64 {
+        cmp::max(scale(1, self.femtoseconds_per_tick), 1)
+    }
+}
+
+// Time stamp counter ticks per millisecond, zero before `init`
+pub fn ticks_per_ms() -> u64 {
+    TICKS_PER_MS.load(Ordering::Relaxed) as u64
+}
+
+// The TSC ticks at a constant rate in all power states, so it can keep time
+pub fn is_invariant() -> bool {
+    CpuId::new().get_extended_function_info()
+        .map_or(false, |info| info.has_invariant_tsc())
+}
+
+// The frequency derived from the crystal clock in CPUID leaf 0x15. `None` if the CPU doesn't
+// report the crystal frequency, many only report the ratio.
+fn crystal_ticks_per_ms() -> Option<u64> {
+    let info = CpuId::new().get_tsc_info()?;
+    let denominator = info.get_tsc_ratio_denominator() as u64;
+    let numerator = info.get_tsc_ratio_numerator() as u64;
+    // raw-cpuid doesn't expose the crystal frequency in ECX
+    let crystal_hz = raw_cpuid::cpuid2(CPUID_TSC_INFO, 0).ecx as u64;
+    if denominator == 0 || numerator == 0 || crystal_hz == 0 {
+        return None;
+    }
+    Some(crystal_hz * numerator / denominator / 1000)
+}
+
+// The processor base frequency in CPUID leaf 0x16. It's rounded to MHz and the TSC doesn't have
+// to run at it, so it's only good to check the TSC frequency for plausibility.
+fn base_frequency_ticks_per_ms() -> Option<u64> {
+    match CpuId::new().get_processor_frequency_info() {
+        Some(ref info) if info.processor_base_frequency() != 0 =>
+            Some(info.processor_base_frequency() as u64 * 1000),
+        _ => None,
+    }
+}
+
+// Determines the TSC frequency from the crystal clock or by calibrating it with
+// `super::calibrate`
+pub fn init() -> FrequencySource {
+    let (ticks_per_ms, source) = match crystal_ticks_per_ms() {
+        Some(ticks_per_ms) => (ticks_per_ms, FrequencySource::CrystalClock),
+        None => (super::calibrate(rdtsc), FrequencySource::Calibrated),
+    };
+    if let Some(base) = base_frequency_ticks_per_ms() {
+        let difference = if ticks_per_ms > base { ticks_per_ms - base } else { base - ticks_per_ms };
+        if difference * 100 > base * BASE_FREQUENCY_TOLERANCE {
+            warn!("TSC runs at {} kHz, but the processor base frequency is {} kHz", ticks_per_ms, base);
+        }
+    }
+    TICKS_PER_MS.store(ticks_per_ms as usize, Ordering::Relaxed);
+    source
+}
+
+// Returns a clock continuing from `now` nanoseconds if the TSC is invariant and `init` found its
+// frequency. Only one clock can be created.
+pub fn clock(now: u64) -> Option<&'static TscClock> {
+    let ticks_per_ms = ticks_per_ms();
+    if !is_invariant() || ticks_per_ms == 0 {
+        return None;
+    }
+    assert!(CLOCK.try().is_none(), "the TSC clock already exists");
+    Some(CLOCK.call_once(|| TscClock {
+        start_ticks: rdtsc(),
+        start_nanoseconds: now,
+        femtoseconds_per_tick: 1_000_000_000_000 / ticks_per_ms,
+    }))
+}